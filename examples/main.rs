@@ -6,20 +6,21 @@ use std::convert::TryInto;
 use std::sync::Arc;
 use std::time::Instant;
 
-use egui_vulkano::UpdateTexturesResult;
 use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents};
 use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
 use vulkano::device::{Device, DeviceExtensions};
 use vulkano::format::Format;
-use vulkano::image::view::ImageView;
-use vulkano::image::{ImageAccess, ImageUsage, SwapchainImage};
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{
+    ImageAccess, ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage, SwapchainImage,
+};
 use vulkano::instance::Instance;
 use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
 use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
 use vulkano::pipeline::graphics::viewport::Viewport;
 use vulkano::pipeline::graphics::viewport::ViewportState;
-use vulkano::pipeline::GraphicsPipeline;
+use vulkano::pipeline::{GraphicsPipeline, Pipeline};
 use vulkano::render_pass::{Framebuffer, RenderPass, Subpass};
 use vulkano::swapchain::{AcquireError, ColorSpace, Swapchain, PresentMode, SwapchainCreationError};
 use vulkano::sync::{GpuFuture, FlushError};
@@ -58,6 +59,28 @@ mod vs {
     }
 }
 
+// Rotates the triangle drawn into the 3D viewport window
+mod viewport_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec2 position;
+
+            layout(push_constant) uniform PushConstants {
+                float angle;
+            } pushConstants;
+
+            void main() {
+                float s = sin(pushConstants.angle);
+                float c = cos(pushConstants.angle);
+                gl_Position = vec4(mat2(c, s, -s, c) * position, 0.0, 1.0);
+            }
+        "
+    }
+}
+
 mod fs {
     vulkano_shaders::shader! {
         ty: "fragment",
@@ -94,6 +117,7 @@ fn main() {
 
     let (physical, queue_family) = PhysicalDevice::enumerate(&instance)
         .filter(|&p| p.supported_extensions().is_superset_of(&device_extensions) )
+        .filter(|&p| p.supported_features().is_superset_of(&egui_vulkano::required_features()))
         .filter_map(|p| p.queue_families()
                             .find(|&q| q.supports_graphics() && surface.is_supported(q).unwrap_or(false) )
                             .map(|q| (p, q))
@@ -120,7 +144,10 @@ fn main() {
     let (device, mut queues) = Device::new(
         physical, 
         &features,                
-        &physical.required_extensions().union(&device_extensions),
+        &physical
+            .required_extensions()
+            .union(&device_extensions)
+            .union(&egui_vulkano::required_device_extensions()),
         [(queue_family, 0.5)].iter().cloned(),
     )
     .unwrap();
@@ -213,6 +240,59 @@ fn main() {
         .build(device.clone())
         .unwrap();
 
+    // egui 0.17 has no paint callbacks, so custom rendering shown in the gui is drawn into an
+    // image of its own, which is registered with the painter and shown like any other texture
+    let viewport_render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                load: Clear,
+                store: Store,
+                format: Format::R8G8B8A8_SRGB,
+                samples: 1,
+            }
+        },
+        pass: { color: [color], depth_stencil: {} }
+    )
+    .unwrap();
+    let viewport_image = StorageImage::with_usage(
+        device.clone(),
+        ImageDimensions::Dim2d {
+            width: 256,
+            height: 256,
+            array_layers: 1,
+        },
+        Format::R8G8B8A8_SRGB,
+        ImageUsage {
+            color_attachment: true,
+            sampled: true,
+            ..ImageUsage::none()
+        },
+        ImageCreateFlags::none(),
+        [queue.family()],
+    )
+    .unwrap();
+    let viewport_framebuffer = Framebuffer::start(viewport_render_pass.clone())
+        .add(ImageView::new(viewport_image.clone()).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let viewport_vs = viewport_vs::load(device.clone()).unwrap();
+    let viewport_pipeline = GraphicsPipeline::start()
+        .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+        .vertex_shader(viewport_vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [256.0, 256.0],
+            depth_range: 0.0..1.0,
+        }]))
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .render_pass(Subpass::from(viewport_render_pass.clone(), 0).unwrap())
+        .build(device.clone())
+        .unwrap();
+    let start_time = Instant::now();
+
     let mut viewport = Viewport {
         origin: [0.0, 0.0],
         dimensions: [0.0, 0.0],
@@ -220,6 +300,8 @@ fn main() {
     };
 
     let mut framebuffers = window_size_dependent_setup(&images, render_pass.clone(), &mut viewport);
+    // Number of frames that can be in flight at once, used to know when old egui textures can be dropped
+    let frames_in_flight = images.len();
 
     let mut recreate_swapchain = false;
 
@@ -228,21 +310,58 @@ fn main() {
     //Set up everything need to draw the gui
     let window = surface.window();
     let egui_ctx = egui::Context::default();
-    let mut egui_winit = egui_winit::State::new(4096, window);
+    // Tell egui how large the font atlas may get on this device
+    let mut egui_winit = egui_winit::State::new(egui_vulkano::max_texture_side(&physical), window);
 
-    let mut egui_painter = egui_vulkano::Painter::new(
+    // User textures repeat, so they can be tiled by drawing them with UVs larger than 1
+    let painter_builder = egui_vulkano::Painter::builder(
         device.clone(),
         queue.clone(),
         Subpass::from(render_pass.clone(), 1).unwrap(),
     )
-    .unwrap();
+    .user_texture_wrap_mode(egui_vulkano::TextureWrapMode::Repeat);
+    // Settings whose features or extensions the device doesn't have fall back
+    if !device.enabled_features().is_superset_of(&painter_builder.required_features())
+        || !device
+            .enabled_extensions()
+            .is_superset_of(&painter_builder.required_device_extensions())
+    {
+        println!("Some painter settings aren't supported by this device and fall back");
+    }
+    let mut egui_painter = painter_builder.build().unwrap();
 
     //Set up some window to look at for the test
 
     let mut egui_test = egui_demo_lib::ColorTest::default();
     let mut demo_windows = egui_demo_lib::DemoWindows::default();
     let mut egui_bench = Benchmark::new(1000);
+    let mut crisp_text = false;
+    let mut reuse_idle_geometry = false;
+    let mut needs_repaint = true;
+    let mut last_screen_rect = None;
+    let viewport_texture = egui_painter
+        .register_user_image(
+            ImageView::new(viewport_image.clone()).unwrap(),
+            Default::default(),
+        )
+        .unwrap();
     let mut my_texture = egui_ctx.load_texture("my_texture", egui::ColorImage::example());
+    // Always shows the latest font atlas, even after egui reallocated it
+    let font_atlas = egui_painter.font_texture_id();
+
+    // Same texture with mipmaps and anisotropic filtering, to compare how it looks when scaled down
+    let my_mipmapped_texture =
+        egui_ctx.load_texture("my_mipmapped_texture", egui::ColorImage::example());
+    egui_painter
+        .set_texture_options(
+            my_mipmapped_texture.id(),
+            egui_vulkano::TextureOptions {
+                anisotropy: Some(16),
+                mipmaps: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -313,7 +432,16 @@ fn main() {
                 .unwrap();
 
                 let frame_start = Instant::now();
-                egui_ctx.begin_frame(egui_winit.take_egui_input(surface.window()));
+                let raw_input = egui_winit.take_egui_input(surface.window());
+                // The shapes only stay the same if egui asked for no repaint and nothing happened since.
+                // The gui (including the benchmark plot) freezes while idle, the timings of these frames
+                // show up in the plot once it's redrawn.
+                let idle = reuse_idle_geometry
+                    && !needs_repaint
+                    && raw_input.events.is_empty()
+                    && last_screen_rect == raw_input.screen_rect;
+                last_screen_rect = raw_input.screen_rect;
+                egui_ctx.begin_frame(raw_input);
                 demo_windows.ui(&egui_ctx);
 
                 egui::Window::new("Color test")
@@ -323,33 +451,91 @@ fn main() {
                     });
 
                 egui::Window::new("Settings").show(&egui_ctx, |ui| {
+                    ui.checkbox(&mut crisp_text, "Snap vertices to pixels for crisp text");
+                    ui.checkbox(&mut reuse_idle_geometry, "Reuse the geometry of idle frames");
                     egui_ctx.settings_ui(ui);
                 });
+                egui_painter.set_pixel_snapping(crisp_text);
 
+                let stats = egui_painter.last_stats();
                 egui::Window::new("Benchmark")
                     .default_height(600.0)
                     .show(&egui_ctx, |ui| {
                         egui_bench.draw(ui);
+                        ui.label(format!(
+                            "Last frame: {} meshes in {} draw calls, {} vertices, {} indices, {} texture bytes uploaded, {} unchanged uploads skipped",
+                            stats.meshes, stats.draw_calls, stats.vertices, stats.indices, stats.texture_bytes_uploaded, stats.texture_uploads_skipped
+                        ));
                     });
 
                 egui::Window::new("Texture test").show(&egui_ctx, |ui| {
                     ui.image(my_texture.id(), (200.0, 200.0));
+                    ui.label("Scaled down without and with mipmaps:");
+                    ui.horizontal(|ui| {
+                        ui.image(my_texture.id(), (40.0, 20.0));
+                        ui.image(my_mipmapped_texture.id(), (40.0, 20.0));
+                    });
+                    ui.label("Tiled 3x2 times:");
+                    ui.add(egui::Image::new(my_texture.id(), (120.0, 40.0)).uv(
+                        egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(3.0, 2.0)),
+                    ));
                     if ui.button("Reload texture").clicked() {
                         // previous TextureHandle is dropped, causing egui to free the texture:
                         my_texture = egui_ctx.load_texture("my_texture", egui::ColorImage::example());
                     }
                 });
 
+                egui::Window::new("3D viewport").show(&egui_ctx, |ui| {
+                    ui.image(viewport_texture, (256.0, 256.0));
+                });
+
+                let font_atlas_size = egui_painter
+                    .font_texture_view()
+                    .map(|view| view.image().dimensions().width_height());
+                egui::Window::new("Font atlas")
+                    .default_open(false)
+                    .vscroll(true)
+                    .show(&egui_ctx, |ui| match font_atlas_size {
+                        Some([width, height]) => {
+                            ui.label(format!("{}x{} pixels", width, height));
+                            ui.image(font_atlas, (width as f32, height as f32));
+                        }
+                        None => {
+                            ui.label("Not uploaded yet");
+                        }
+                    });
+
                 // Get the shapes from egui
                 let egui_output = egui_ctx.end_frame();
+                needs_repaint = egui_output.needs_repaint;
                 let platform_output = egui_output.platform_output;
                 egui_winit.handle_platform_output(surface.window(), &egui_ctx, platform_output);
 
-                let result = egui_painter
+                let textures = egui_painter
                     .update_textures(egui_output.textures_delta, &mut builder)
                     .expect("egui texture error");
 
-                let wait_for_last_frame = result == UpdateTexturesResult::Changed;
+                // Render the 3D viewport before the render pass that shows it in the gui
+                builder
+                    .begin_render_pass(
+                        viewport_framebuffer.clone(),
+                        SubpassContents::Inline,
+                        vec![[0.1, 0.1, 0.1, 1.0].into()],
+                    )
+                    .unwrap()
+                    .bind_pipeline_graphics(viewport_pipeline.clone())
+                    .push_constants(
+                        viewport_pipeline.layout().clone(),
+                        0,
+                        viewport_vs::ty::PushConstants {
+                            angle: start_time.elapsed().as_secs_f32(),
+                        },
+                    )
+                    .bind_vertex_buffers(0, vertex_buffer.clone())
+                    .draw(vertex_buffer.len().try_into().unwrap(), 1, 0, 0)
+                    .unwrap()
+                    .end_render_pass()
+                    .unwrap();
 
                 // Do your usual rendering
                 builder
@@ -370,14 +556,14 @@ fn main() {
                 // Automatically start the next render subpass and draw the gui
                 let size = surface.window().inner_size();
                 let sf: f32 = surface.window().scale_factor() as f32;
-                egui_painter
-                    .draw(
-                        &mut builder,
-                        [(size.width as f32) / sf, (size.height as f32) / sf],
-                        &egui_ctx,
-                        egui_output.shapes,
-                    )
-                    .unwrap();
+                let size_points = [(size.width as f32) / sf, (size.height as f32) / sf];
+                if idle && !textures.font_atlas_changed {
+                    egui_painter.draw_unchanged(&mut builder, size_points).unwrap();
+                } else {
+                    egui_painter
+                        .draw(&mut builder, size_points, &egui_ctx, egui_output.shapes)
+                        .unwrap();
+                }
 
                 egui_bench.push(frame_start.elapsed().as_secs_f64());
 
@@ -386,12 +572,6 @@ fn main() {
 
                 let command_buffer = builder.build().unwrap();
 
-                if wait_for_last_frame {
-                    if let Some(FrameEndFuture::FenceSignalFuture(ref mut f)) = previous_frame_end {
-                        f.wait(None).unwrap();
-                    }
-                }
-
                 let future = previous_frame_end
                     .take()
                     .unwrap()
@@ -402,6 +582,9 @@ fn main() {
                     .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)
                     .then_signal_fence_and_flush();
 
+                // Drop egui textures that can't be in use by the GPU anymore
+                egui_painter.collect_garbage(frames_in_flight);
+
                 match future {
                     Ok(future) => {
                         previous_frame_end = Some(FrameEndFuture::FenceSignalFuture(future));
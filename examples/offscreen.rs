@@ -0,0 +1,203 @@
+/// Renders the gui into an offscreen `R16G16B16A16_SFLOAT` image without a window
+/// and reads the result back to the CPU.
+/// Differences to the main example:
+/// * No swapchain, the color attachment is an `AttachmentImage` with a linear float format
+/// * The painter is created with `OutputColorSpace::Linear`
+/// * The gui is tessellated once and drawn into two images, like a window and a mirrored capture
+use egui::{Pos2, Rect, Vec2};
+use egui_vulkano::OutputColorSpace;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents};
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{AttachmentImage, ImageUsage};
+use vulkano::instance::{Instance, InstanceExtensions};
+use vulkano::render_pass::{Framebuffer, Subpass};
+use vulkano::sync::GpuFuture;
+use vulkano::{sync, Version};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+fn main() {
+    let instance = Instance::new(None, Version::V1_1, &InstanceExtensions::none(), None).unwrap();
+
+    let (physical, queue_family) = PhysicalDevice::enumerate(&instance)
+        .filter_map(|p| {
+            p.queue_families()
+                .find(|q| q.supports_graphics())
+                .map(|q| (p, q))
+        })
+        .next()
+        .expect("no device with a graphics queue found");
+
+    println!("Using device: {}", physical.properties().device_name);
+
+    // Enable what the painter needs, plus the optional features it can use if supported
+    let features = egui_vulkano::required_features().union(
+        &physical
+            .supported_features()
+            .intersection(&egui_vulkano::optional_features()),
+    );
+    let (device, mut queues) = Device::new(
+        physical,
+        &features,
+        &egui_vulkano::required_device_extensions(),
+        [(queue_family, 0.5)].iter().cloned(),
+    )
+    .unwrap();
+    let queue = queues.next().unwrap();
+
+    let format = Format::R16G16B16A16_SFLOAT;
+    let render_pass = vulkano::ordered_passes_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                load: Clear,
+                store: Store,
+                format: format,
+                samples: 1,
+            }
+        },
+        passes: [
+            { color: [color], depth_stencil: {}, input: [] },
+            { color: [color], depth_stencil: {}, input: [] } // Second subpass to draw egui
+        ]
+    )
+    .unwrap();
+
+    // One image standing in for the window and one for a capture of it
+    let images: Vec<_> = (0..2)
+        .map(|_| {
+            AttachmentImage::with_usage(
+                device.clone(),
+                [WIDTH, HEIGHT],
+                format,
+                ImageUsage {
+                    color_attachment: true,
+                    transfer_source: true,
+                    ..ImageUsage::none()
+                },
+            )
+            .unwrap()
+        })
+        .collect();
+
+    // The float attachment stores linear colors, so the shader output is written unchanged
+    let mut egui_painter = egui_vulkano::Painter::builder(
+        device.clone(),
+        queue.clone(),
+        Subpass::from(render_pass.clone(), 1).unwrap(),
+    )
+    .color_space(OutputColorSpace::Linear)
+    .build()
+    .unwrap();
+
+    let egui_ctx = egui::Context::default();
+    let mut raw_input = egui::RawInput {
+        screen_rect: Some(Rect::from_min_size(
+            Pos2::ZERO,
+            Vec2::new(WIDTH as f32, HEIGHT as f32),
+        )),
+        pixels_per_point: Some(1.0),
+        ..Default::default()
+    };
+    egui_painter.context_options().apply(&mut raw_input);
+    let egui_output = egui_ctx.run(raw_input, |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Offscreen egui");
+            ui.label("Rendered into an R16G16B16A16_SFLOAT image");
+        });
+    });
+
+    let readbacks: Vec<_> = images
+        .iter()
+        .map(|_| {
+            CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::transfer_destination(),
+                false,
+                (0..WIDTH * HEIGHT * 4).map(|_| 0u16),
+            )
+            .unwrap()
+        })
+        .collect();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        device.clone(),
+        queue.family(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    egui_painter
+        .update_textures(egui_output.textures_delta, &mut builder)
+        .expect("egui texture error");
+
+    // Tessellate once, then draw the same meshes into each image
+    let clipped_meshes = egui_painter.tessellate(&egui_ctx, egui_output.shapes);
+    for (image, readback) in images.iter().zip(&readbacks) {
+        let framebuffer = Framebuffer::start(render_pass.clone())
+            .add(ImageView::new(image.clone()).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        builder
+            .begin_render_pass(
+                framebuffer,
+                SubpassContents::Inline,
+                vec![[0.0, 0.0, 0.0, 1.0].into()],
+            )
+            .unwrap();
+
+        egui_painter
+            .draw_meshes(&mut builder, [WIDTH as f32, HEIGHT as f32], &clipped_meshes)
+            .unwrap();
+
+        builder.end_render_pass().unwrap();
+        builder
+            .copy_image_to_buffer(image.clone(), readback.clone())
+            .unwrap();
+    }
+
+    let command_buffer = builder.build().unwrap();
+    sync::now(device.clone())
+        .then_execute(queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    for (name, readback) in ["Window", "Capture"].iter().zip(&readbacks) {
+        let pixels = readback.read().unwrap();
+        let lit = pixels
+            .chunks(4)
+            .filter(|p| p[..3].iter().any(|&c| f32_from_f16(c) > 0.0))
+            .count();
+        let center = ((HEIGHT / 2 * WIDTH + WIDTH / 2) * 4) as usize;
+        let center_color: Vec<f32> = pixels[center..center + 4]
+            .iter()
+            .map(|&c| f32_from_f16(c))
+            .collect();
+
+        println!("{}: {} of {} pixels are lit", name, lit, WIDTH * HEIGHT);
+        println!("{}: linear color at the center: {:?}", name, center_color);
+    }
+}
+
+/// Convert an IEEE 754 half precision float to f32
+fn f32_from_f16(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,186 @@
+//! Drawing every texture from one descriptor set with an array of textures,
+//! selecting the slot of each draw call with a push constant.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use egui::TextureId;
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::{
+    DescriptorSetCreationError, PersistentDescriptorSet, WriteDescriptorSet,
+};
+use vulkano::device::Device;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::graphics::GraphicsPipeline;
+use vulkano::pipeline::Pipeline;
+use vulkano::sampler::Sampler;
+
+use crate::{ContextId, DrawError, Painter, PipelineVariant, UpdateTexturesError};
+
+/// Size of the texture array, has to match `TEXTURE_SLOTS` in `shaders::fs_bindless`
+pub(crate) const TEXTURE_SLOTS: u32 = 256;
+
+/// Whether the device can index an array of [`TEXTURE_SLOTS`] textures in the fragment shader
+pub(crate) fn is_supported(device: &Device) -> bool {
+    let properties = device.physical_device().properties();
+    device
+        .enabled_features()
+        .shader_sampled_image_array_dynamic_indexing
+        && properties.max_per_stage_descriptor_samplers >= TEXTURE_SLOTS
+        && properties.max_per_stage_descriptor_sampled_images >= TEXTURE_SLOTS
+        && properties.max_descriptor_set_samplers >= TEXTURE_SLOTS
+        && properties.max_descriptor_set_sampled_images >= TEXTURE_SLOTS
+}
+
+/// Slots of the texture array, shared by the textures of all contexts
+#[derive(Default)]
+pub(crate) struct BindlessTextures {
+    slots: HashMap<(ContextId, TextureId), u32>,
+    /// View and sampler in each slot, `None` for free slots
+    textures: Vec<Option<(Arc<dyn ImageViewAbstract>, Arc<Sampler>)>>,
+    /// Descriptor set of the current slots, `None` until it's needed after a change
+    set: Option<Arc<PersistentDescriptorSet>>,
+}
+
+impl BindlessTextures {
+    /// Get the slot of a texture
+    pub(crate) fn slot(&self, context: ContextId, texture_id: TextureId) -> Option<u32> {
+        self.slots.get(&(context, texture_id)).copied()
+    }
+
+    /// Put a texture into its slot, or the first free one.
+    /// Returns `false` if all slots are taken.
+    fn insert(
+        &mut self,
+        key: (ContextId, TextureId),
+        texture: (Arc<dyn ImageViewAbstract>, Arc<Sampler>),
+    ) -> bool {
+        let slot = match self.slots.get(&key) {
+            Some(&slot) => slot as usize,
+            None => match self.textures.iter().position(Option::is_none) {
+                Some(slot) => slot,
+                None if self.textures.len() < TEXTURE_SLOTS as usize => {
+                    self.textures.push(None);
+                    self.textures.len() - 1
+                }
+                None => return false,
+            },
+        };
+        self.slots.insert(key, slot as u32);
+        self.textures[slot] = Some(texture);
+        true
+    }
+
+    /// Free the slot of a texture for reuse. Returns `false` if it had none.
+    fn remove(&mut self, key: (ContextId, TextureId)) -> bool {
+        match self.slots.remove(&key) {
+            Some(slot) => {
+                self.textures[slot as usize] = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Build the descriptor set of the current slots,
+    /// or return `None` if no slot is taken
+    fn build_set(
+        &mut self,
+        layout: Arc<DescriptorSetLayout>,
+    ) -> Result<Option<Arc<PersistentDescriptorSet>>, DescriptorSetCreationError> {
+        // Every element of the array has to be valid, so free slots repeat a taken one
+        let filler = match self.textures.iter().flatten().next() {
+            Some(texture) => texture.clone(),
+            None => return Ok(None),
+        };
+        let textures = (0..TEXTURE_SLOTS as usize).map(|slot| {
+            self.textures
+                .get(slot)
+                .cloned()
+                .flatten()
+                .unwrap_or_else(|| filler.clone())
+        });
+        let set = PersistentDescriptorSet::new(
+            layout,
+            [WriteDescriptorSet::image_view_sampler_array(0, 0, textures)],
+        )?;
+        self.set = Some(set.clone());
+        Ok(Some(set))
+    }
+}
+
+impl Painter {
+    /// Put a texture of the selected context into the texture array, if bindless textures are on.
+    /// Textures that don't fit are drawn with their own descriptor set instead.
+    pub(crate) fn insert_bindless_texture(
+        &mut self,
+        texture_id: TextureId,
+        view: Arc<dyn ImageViewAbstract>,
+        sampler: Arc<Sampler>,
+    ) {
+        let key = (self.context, texture_id);
+        if let Some(bindless) = &mut self.bindless {
+            if bindless.insert(key, (view, sampler)) {
+                let old_set = bindless.set.take();
+                self.retire(None, old_set);
+            }
+        }
+    }
+
+    /// Free the slot of a texture of the given context
+    pub(crate) fn remove_bindless_texture(&mut self, context: ContextId, texture_id: TextureId) {
+        if let Some(bindless) = &mut self.bindless {
+            if bindless.remove((context, texture_id)) {
+                let old_set = bindless.set.take();
+                self.retire(None, old_set);
+            }
+        }
+    }
+
+    /// Free the slots of all textures of the given context
+    pub(crate) fn remove_bindless_context(&mut self, context: ContextId) {
+        let texture_ids: Vec<TextureId> = match &self.bindless {
+            Some(bindless) => bindless
+                .slots
+                .keys()
+                .filter(|(c, _)| *c == context)
+                .map(|&(_, texture_id)| texture_id)
+                .collect(),
+            None => return,
+        };
+        for texture_id in texture_ids {
+            self.remove_bindless_texture(context, texture_id);
+        }
+    }
+
+    /// Get the bindless pipeline for the subpass of `pipeline` and the descriptor set of the
+    /// texture array, or `None` if bindless textures are off or no texture has a slot
+    pub(crate) fn bindless_state(
+        &mut self,
+        pipeline: &Arc<GraphicsPipeline>,
+    ) -> Result<Option<(Arc<GraphicsPipeline>, Arc<PersistentDescriptorSet>)>, DrawError> {
+        if self.bindless.is_none() || self.is_offscreen_pipeline(pipeline) {
+            return Ok(None);
+        }
+        let variant = PipelineVariant {
+            bindless: true,
+            ..PipelineVariant::default()
+        };
+        let bindless_pipeline = self.pipeline_variant(pipeline, variant)?;
+        let bindless = self.bindless.as_mut().unwrap();
+
+        let set = match &bindless.set {
+            Some(set) => set.clone(),
+            None => {
+                let layout = bindless_pipeline.layout().descriptor_set_layouts()[0].clone();
+                match bindless
+                    .build_set(layout)
+                    .map_err(UpdateTexturesError::from)?
+                {
+                    Some(set) => set,
+                    None => return Ok(None),
+                }
+            }
+        };
+        Ok(Some((bindless_pipeline, set)))
+    }
+}
@@ -0,0 +1,161 @@
+//! Recording the gui into a secondary command buffer that's replayed
+//! as long as its shapes don't change, e.g. for dashboards that rarely repaint.
+use std::sync::Arc;
+
+use egui::epaint::ClippedShape;
+use egui::{Context, TextureId};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, SecondaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::DescriptorSet;
+use vulkano::pipeline::graphics::GraphicsPipeline;
+use vulkano::pipeline::Pipeline;
+
+use crate::push_descriptors::PushedTexture;
+use crate::{is_drawable_size, quarter_turns, window_viewport, DrawError, Painter};
+
+/// Command buffer recorded by [`Painter::draw_cached`] and what it was recorded from
+pub(crate) struct CachedDraw {
+    shapes: Vec<ClippedShape>,
+    window_size_points: [f32; 2],
+    pixels_per_point: f32,
+    pipeline: Arc<GraphicsPipeline>,
+    /// Descriptor set or pushed view and sampler of each texture the command buffer draws
+    /// when it was recorded
+    textures: Vec<(
+        TextureId,
+        Option<Arc<dyn DescriptorSet + Send + Sync>>,
+        Option<PushedTexture>,
+    )>,
+    command_buffer: Arc<SecondaryAutoCommandBuffer>,
+}
+
+impl Painter {
+    /// Returns a secondary command buffer that draws the shapes in the painter's subpass,
+    /// which is only recorded again when the shapes, the window size, `pixels_per_point`,
+    /// the pipeline or the textures it draws change. Otherwise the buffer recorded by the
+    /// previous call for the selected context is returned, without tessellating the shapes
+    /// or writing any geometry, e.g. for dashboards that only repaint when their data changes.
+    ///
+    /// Execute it with `execute_commands` in a subpass begun with
+    /// `SubpassContents::SecondaryCommandBuffers`, as often as needed: it's recorded with
+    /// [`CommandBufferUsage::SimultaneousUse`], so it can be in several frames in flight.
+    /// Compare the returned buffers with [`Arc::ptr_eq`] to find out whether it was recorded again.
+    ///
+    /// The buffer sets its own viewport, since secondary command buffers don't inherit it:
+    /// the target viewport if one is set, otherwise the whole window of `window_size_points`.
+    ///
+    /// Texture deltas still have to be passed to [`Painter::update_textures`] as usual,
+    /// textures freed by a frame are released by the next call of it.
+    /// The geometry is written to buffers of its own, so [`Painter::set_frames_in_flight`]
+    /// and [`PainterBuilder::device_local_geometry`](crate::PainterBuilder::device_local_geometry)
+    /// don't apply. Call [`Painter::invalidate_cached_draws`] after changing other settings
+    /// that affect drawing, e.g. [`Painter::set_ui_transform`].
+    pub fn draw_cached(
+        &mut self,
+        window_size_points: [f32; 2],
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Result<Arc<SecondaryAutoCommandBuffer>, DrawError> {
+        self.use_pixels_per_point_of(egui_ctx);
+        if let Some(cached) = self.cached_draws.get(&self.context) {
+            let unchanged = cached.shapes == clipped_shapes
+                && cached.window_size_points == window_size_points
+                && cached.pixels_per_point == self.pixels_per_point
+                && Arc::ptr_eq(&cached.pipeline, &self.pipeline)
+                && cached.textures.iter().all(|(texture_id, set, pushed)| {
+                    same_set(set.as_ref(), self.texture_set(*texture_id).as_ref())
+                        && same_pushed(pushed.as_ref(), self.pushed_texture(*texture_id).as_ref())
+                });
+            if unchanged {
+                return Ok(cached.command_buffer.clone());
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(shapes = clipped_shapes.len(), "recording cached draw");
+        let shapes = clipped_shapes.clone();
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        let (mut geometry, triangles) = self.merge_meshes(&clipped_meshes)?;
+        // Pool chunks stay untouched while the command buffer holds them,
+        // unlike the buffers of frames in flight
+        if !geometry.batches.is_empty() {
+            geometry.buffers = Some(self.write_pooled_buffers(&triangles)?);
+        }
+        let textures = geometry
+            .batches
+            .iter()
+            .map(|batch| {
+                (
+                    batch.texture_id,
+                    self.texture_set(batch.texture_id),
+                    self.pushed_texture(batch.texture_id),
+                )
+            })
+            .collect();
+
+        let pipeline = self.pipeline.clone();
+        let mut builder = AutoCommandBufferBuilder::secondary_graphics(
+            self.device.clone(),
+            self.queue.family(),
+            CommandBufferUsage::SimultaneousUse,
+            pipeline.subpass().clone(),
+        )?;
+        // Secondary command buffers don't inherit the viewport of the primary one
+        if self.target_viewport.is_none() && is_drawable_size(window_size_points) {
+            builder.set_viewport(
+                0,
+                [window_viewport(
+                    window_size_points,
+                    self.pixels_per_point,
+                    quarter_turns(self.surface_transform),
+                )],
+            );
+        }
+        self.record_geometry(&mut builder, pipeline.clone(), window_size_points, geometry)?;
+        let command_buffer = Arc::new(builder.build()?);
+
+        self.cached_draws.insert(
+            self.context,
+            CachedDraw {
+                shapes,
+                window_size_points,
+                pixels_per_point: self.pixels_per_point,
+                pipeline,
+                textures,
+                command_buffer: command_buffer.clone(),
+            },
+        );
+        Ok(command_buffer)
+    }
+
+    /// Records the command buffers of the next [`Painter::draw_cached`] of every context again,
+    /// e.g. after changing a setting that affects how the gui is drawn.
+    pub fn invalidate_cached_draws(&mut self) {
+        self.cached_draws.clear();
+    }
+}
+
+/// Whether two optional descriptor sets are the same set
+fn same_set(
+    a: Option<&Arc<dyn DescriptorSet + Send + Sync>>,
+    b: Option<&Arc<dyn DescriptorSet + Send + Sync>>,
+) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const (),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Whether two optional pushed textures have the same view and sampler
+fn same_pushed(a: Option<&PushedTexture>, b: Option<&PushedTexture>) -> bool {
+    match (a, b) {
+        (Some((a_view, a_sampler)), Some((b_view, b_sampler))) => {
+            Arc::as_ptr(a_view) as *const () == Arc::as_ptr(b_view) as *const ()
+                && Arc::ptr_eq(a_sampler, b_sampler)
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,169 @@
+//! Shaders of your own that replace the painter's, e.g. for post effects on the gui,
+//! with descriptors of your own in set 1.
+use std::sync::Arc;
+
+use vulkano::command_buffer::pool::CommandPoolBuilderAlloc;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor_set::layout::{DescriptorSetLayout, DescriptorType};
+use vulkano::descriptor_set::{DescriptorSet, DescriptorSetWithOffsets};
+use vulkano::device::Device;
+use vulkano::pipeline::graphics::{GraphicsPipeline, GraphicsPipelineCreationError};
+use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+use vulkano::shader::ShaderModule;
+
+use crate::{Painter, PainterCreationError};
+
+/// Set that user descriptors live in, set 0 is the gui's texture
+pub(crate) const USER_SET: u32 = 1;
+
+/// SPIR-V of shaders that replace the painter's own, see [`crate::PainterBuilder::custom_shaders`].
+///
+/// Both stages need an entry point named `main`. The painter binds the texture of each draw call
+/// as a combined image sampler to set 0 binding 0, every other descriptor has to be in set 1.
+#[derive(Clone, Debug, Default)]
+pub struct CustomShaders {
+    /// Replaces the vertex shader, `None` keeps the painter's.
+    /// It has to take the vertex attributes and push constants of `src/shaders/vert.vert`.
+    pub vertex: Option<Vec<u8>>,
+    /// Replaces the fragment shader, `None` keeps the painter's.
+    /// Its inputs have to match the outputs of the vertex shader.
+    /// It gets no specialization constants, so it has to encode its output
+    /// for the color space of the attachment itself.
+    pub fragment: Option<Vec<u8>>,
+}
+
+/// Creates the descriptor set bound to set 1 from its layout, see
+/// [`crate::PainterBuilder::user_descriptor_set`].
+pub type UserDescriptorSetFn =
+    Box<dyn FnMut(Arc<DescriptorSetLayout>) -> Arc<dyn DescriptorSet + Send + Sync> + Send>;
+
+/// Shader modules created from [`CustomShaders`]
+#[derive(Clone, Default)]
+pub(crate) struct ShaderModules {
+    pub(crate) vertex: Option<Arc<ShaderModule>>,
+    pub(crate) fragment: Option<Arc<ShaderModule>>,
+}
+
+impl ShaderModules {
+    /// Create the modules of the custom shaders and check the descriptors they use
+    pub(crate) fn load(
+        device: &Arc<Device>,
+        shaders: &CustomShaders,
+    ) -> Result<Self, PainterCreationError> {
+        let vertex = match &shaders.vertex {
+            Some(spirv) => Some(load_module(device, "vertex", spirv, false)?),
+            None => None,
+        };
+        let fragment = match &shaders.fragment {
+            Some(spirv) => Some(load_module(device, "fragment", spirv, true)?),
+            None => None,
+        };
+        Ok(Self { vertex, fragment })
+    }
+}
+
+/// Create a shader module and check that it only uses the descriptors the painter provides
+fn load_module(
+    device: &Arc<Device>,
+    stage: &'static str,
+    spirv: &[u8],
+    samples_texture: bool,
+) -> Result<Arc<ShaderModule>, PainterCreationError> {
+    let incompatible =
+        |reason: String| Err(PainterCreationError::IncompatibleShader { stage, reason });
+
+    // Safety: vulkano validates the interfaces of the module's entry points against the pipeline,
+    // what the shader does at runtime is up to its author
+    let module = unsafe { ShaderModule::from_bytes(device.clone(), spirv)? };
+    let entry_point = match module.entry_point("main") {
+        Some(entry_point) => entry_point,
+        None => return incompatible("no entry point named `main`".to_owned()),
+    };
+
+    let mut texture = false;
+    for ((set, binding), requirements) in entry_point.descriptor_requirements() {
+        match (set, binding) {
+            (0, 0)
+                if requirements
+                    .descriptor_types
+                    .contains(&DescriptorType::CombinedImageSampler) =>
+            {
+                texture = true
+            }
+            (0, 0) => {
+                return incompatible(format!(
+                "set 0 binding 0 has to be the gui's texture, a combined image sampler, not {:?}",
+                requirements.descriptor_types
+            ))
+            }
+            (USER_SET, _) => (),
+            _ => {
+                return incompatible(format!(
+                    "it uses set {} binding {}, only set 0 binding 0 and set {} are available",
+                    set, binding, USER_SET
+                ))
+            }
+        }
+    }
+    if samples_texture && !texture {
+        return incompatible("it doesn't sample the gui's texture in set 0 binding 0".to_owned());
+    }
+    Ok(module)
+}
+
+/// Describe the errors that custom shaders with mismatching interfaces cause
+pub(crate) fn describe_error(err: GraphicsPipelineCreationError) -> PainterCreationError {
+    match err {
+        GraphicsPipelineCreationError::ShaderStagesMismatch(mismatch) => {
+            PainterCreationError::IncompatibleShader {
+                stage: "fragment",
+                reason: format!(
+                    "its inputs don't match the vertex shader's outputs: {}",
+                    mismatch
+                ),
+            }
+        }
+        GraphicsPipelineCreationError::IncompatibleVertexDefinition(mismatch) => {
+            PainterCreationError::IncompatibleShader {
+                stage: "vertex",
+                reason: format!(
+                    "its inputs don't match the painter's vertices: {}",
+                    mismatch
+                ),
+            }
+        }
+        err => err.into(),
+    }
+}
+
+impl Painter {
+    /// Bind the set created by the user descriptor set callback,
+    /// if there is one and the pipeline's shaders use set 1
+    pub(crate) fn bind_user_descriptor_set<L, P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        pipeline: &Arc<GraphicsPipeline>,
+    ) where
+        P: CommandPoolBuilderAlloc,
+    {
+        let callback = match &mut self.user_descriptor_set {
+            Some(callback) => callback,
+            None => return,
+        };
+        let layout = match pipeline
+            .layout()
+            .descriptor_set_layouts()
+            .get(USER_SET as usize)
+        {
+            Some(layout) => layout.clone(),
+            None => return,
+        };
+        let set = callback(layout);
+        builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            USER_SET,
+            DescriptorSetWithOffsets::new(set, []),
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Debugging aids that visualize how the gui is drawn.
+use std::sync::Arc;
+
+use egui::color::Hsva;
+use egui::epaint::Mesh;
+use egui::{pos2, Color32, Pos2, Rect, TextureId, Vec2};
+use vulkano::command_buffer::pool::CommandPoolBuilderAlloc;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor_set::{DescriptorSetWithOffsets, WriteDescriptorSet};
+use vulkano::pipeline::graphics::viewport::Scissor;
+use vulkano::pipeline::graphics::{GraphicsPipeline, GraphicsPipelineCreationError};
+use vulkano::pipeline::{Pipeline, PipelineBindPoint};
+
+use crate::{shaders, Batch, DrawError, Painter, PipelineVariant, Vertex};
+
+impl Painter {
+    /// Outlines the clip rect of every draw call with a 1 pixel border, e.g. to find out why
+    /// something is clipped. Each draw call gets its own color. Defaults to `false`.
+    ///
+    /// The outlines are drawn on top of the gui in extra draw calls,
+    /// which aren't counted in [`Painter::last_stats`].
+    /// Can be toggled at any time, e.g. with a key binding.
+    pub fn set_debug_clip_rects(&mut self, enabled: bool) {
+        self.debug_clip_rects = enabled;
+    }
+
+    /// Draws the edges of the gui's triangles instead of filling them,
+    /// e.g. to see how egui tessellates shapes and where meshes overdraw. Defaults to `false`.
+    ///
+    /// Uses a second pipeline for the same subpass, which is created on first use.
+    /// Needs the `fill_mode_non_solid` feature, see [`crate::optional_features`],
+    /// without it this setting is ignored.
+    /// [`PainterBuilder::bindless_textures`](crate::PainterBuilder::bindless_textures)
+    /// is not used while drawing wireframes.
+    pub fn set_wireframe(&mut self, enabled: bool) {
+        self.wireframe = enabled && self.device.enabled_features().fill_mode_non_solid;
+    }
+
+    /// Get the wireframe variant of a pipeline if wireframes are drawn,
+    /// which offscreen images never are
+    pub(crate) fn wireframe_pipeline(
+        &mut self,
+        pipeline: &Arc<GraphicsPipeline>,
+    ) -> Result<Option<Arc<GraphicsPipeline>>, GraphicsPipelineCreationError> {
+        if !self.wireframe || self.is_offscreen_pipeline(pipeline) {
+            return Ok(None);
+        }
+        let variant = PipelineVariant {
+            wireframe: true,
+            ..PipelineVariant::default()
+        };
+        self.pipeline_variant(pipeline, variant).map(Some)
+    }
+
+    /// Records the outlines of the clip rects of the batches,
+    /// using the white pixel of the font atlas like egui's own untextured shapes
+    pub(crate) fn record_clip_rects<L, P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        pipeline: &Arc<GraphicsPipeline>,
+        push_pipeline: Option<&Arc<GraphicsPipeline>>,
+        push_constants: shaders::vs::ty::PushConstants,
+        batches: &[Batch],
+        window_size_points: [f32; 2],
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        // The font atlas is pushed unless it has a set, e.g. one of the user's
+        let pushed = match push_pipeline {
+            Some(push_pipeline) => self
+                .pushed_texture(TextureId::default())
+                .map(|texture| (push_pipeline, texture)),
+            None => None,
+        };
+        let texture_set = self.texture_set(TextureId::default());
+        if pushed.is_none() && texture_set.is_none() {
+            return Ok(());
+        }
+
+        let screen = Rect::from_min_size(Pos2::ZERO, Vec2::from(window_size_points));
+        let width = 1.0 / self.pixels_per_point;
+        let mut mesh = Mesh::default();
+        for (index, batch) in batches.iter().enumerate() {
+            let rect = batch.clip.intersect(screen);
+            if !rect.is_positive() {
+                continue;
+            }
+            // Spread the hues of consecutive draw calls far apart
+            let hue = (index as f32 * 0.618).fract();
+            let color = Color32::from(Hsva::new(hue, 1.0, 1.0, 1.0));
+            // The edges lie inside the rect, where its own scissor wouldn't cut them off
+            let inner = rect.shrink(width);
+            for edge in [
+                Rect::from_min_max(rect.min, pos2(rect.max.x, inner.min.y)),
+                Rect::from_min_max(pos2(rect.min.x, inner.max.y), rect.max),
+                Rect::from_min_max(
+                    pos2(rect.min.x, inner.min.y),
+                    pos2(inner.min.x, inner.max.y),
+                ),
+                Rect::from_min_max(
+                    pos2(inner.max.x, inner.min.y),
+                    pos2(rect.max.x, inner.max.y),
+                ),
+            ] {
+                mesh.add_colored_rect(edge, color);
+            }
+        }
+        if mesh.is_empty() {
+            return Ok(());
+        }
+
+        let vertices = self.vertex_pool.chunk(
+            bytemuck::cast_slice::<_, Vertex>(&mesh.vertices)
+                .iter()
+                .cloned(),
+        )?;
+        let indices = self.index_pool_u32.chunk(mesh.indices.iter().copied())?;
+
+        match (pushed, texture_set) {
+            (Some((push_pipeline, (view, sampler))), _) => {
+                builder.bind_pipeline_graphics(push_pipeline.clone());
+                self.bind_user_descriptor_set(builder, push_pipeline);
+                builder.push_constants(push_pipeline.layout().clone(), 0, push_constants);
+                builder.push_descriptor_set(
+                    PipelineBindPoint::Graphics,
+                    push_pipeline.layout().clone(),
+                    0,
+                    [WriteDescriptorSet::image_view_sampler(0, view, sampler)],
+                );
+            }
+            (None, Some(texture_set)) => {
+                builder.bind_pipeline_graphics(pipeline.clone());
+                if push_pipeline.is_some() {
+                    self.bind_user_descriptor_set(builder, pipeline);
+                }
+                builder.push_constants(pipeline.layout().clone(), 0, push_constants);
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    0,
+                    DescriptorSetWithOffsets::new(texture_set, []),
+                );
+            }
+            (None, None) => return Ok(()),
+        }
+        builder.bind_vertex_buffers(0, vertices);
+        builder.bind_index_buffer(indices);
+        builder.set_scissor(0, [Scissor::irrelevant()]);
+        builder.draw_indexed(mesh.indices.len() as u32, 1, 0, 0, 0)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! Reloading custom shaders from SPIR-V files while the app runs, with the `hot-reload` feature.
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use thiserror::Error;
+
+use crate::custom_shaders::ShaderModules;
+use crate::{validate_pipeline, CustomShaders, Painter, PainterCreationError};
+
+#[derive(Error, Debug)]
+pub enum ShaderReloadError {
+    #[error(transparent)]
+    ReadFailed(#[from] std::io::Error),
+    /// The new shaders don't fit the painter or the pipeline couldn't be created,
+    /// the painter keeps drawing with the previous shaders.
+    #[error(transparent)]
+    CreatePipelineFailed(#[from] PainterCreationError),
+}
+
+/// SPIR-V files watched by [`Painter::poll_shader_reload`]
+pub(crate) struct ShaderWatch {
+    vertex_path: PathBuf,
+    fragment_path: PathBuf,
+    /// Modification times of the files when they were last loaded, `None` before the first poll
+    modified: Option<[SystemTime; 2]>,
+}
+
+impl ShaderWatch {
+    pub(crate) fn new(vertex_path: PathBuf, fragment_path: PathBuf) -> Self {
+        Self {
+            vertex_path,
+            fragment_path,
+            modified: None,
+        }
+    }
+}
+
+impl Painter {
+    /// Reloads the shaders watched with
+    /// [`PainterBuilder::watch_shaders`](crate::PainterBuilder::watch_shaders) if either file
+    /// changed since the last poll, e.g. once per frame while iterating on a custom shader.
+    /// Returns whether the pipelines were rebuilt.
+    ///
+    /// The pipelines of all contexts are rebuilt before any of them is replaced,
+    /// so if the new shaders fail to load or don't fit the painter, the error is returned and the
+    /// painter keeps drawing with the previous shaders. The same files aren't loaded again until
+    /// they change. Command buffers that were recorded with the old pipelines keep them alive,
+    /// so frames in flight aren't affected.
+    pub fn poll_shader_reload(&mut self) -> Result<bool, ShaderReloadError> {
+        let watch = match &mut self.shader_watch {
+            Some(watch) => watch,
+            None => return Ok(false),
+        };
+        let modified = [
+            std::fs::metadata(&watch.vertex_path)?.modified()?,
+            std::fs::metadata(&watch.fragment_path)?.modified()?,
+        ];
+        if watch.modified == Some(modified) {
+            return Ok(false);
+        }
+        watch.modified = Some(modified);
+        let shaders = CustomShaders {
+            vertex: Some(std::fs::read(&watch.vertex_path)?),
+            fragment: Some(std::fs::read(&watch.fragment_path)?),
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!("reloading shaders");
+        let modules = ShaderModules::load(&self.device, &shaders)?;
+        let previous_shaders = self.custom_shaders.replace(modules);
+        let previous_pipelines = std::mem::take(&mut self.pipelines);
+        match self.rebuild_pipelines() {
+            Ok(()) => {
+                self.pipeline_variants.clear();
+                Ok(true)
+            }
+            Err(err) => {
+                self.custom_shaders = previous_shaders;
+                self.pipelines = previous_pipelines;
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Create the pipelines of all contexts with the current shaders,
+    /// replacing the old ones only if all of them could be created
+    fn rebuild_pipelines(&mut self) -> Result<(), PainterCreationError> {
+        let pipeline = self.pipeline_for(self.pipeline.subpass().clone())?;
+        validate_pipeline(&pipeline)?;
+
+        let subpasses: Vec<_> = self
+            .inactive_contexts
+            .iter()
+            .filter_map(|(&context, state)| {
+                let pipeline = state.pipeline.as_ref()?;
+                Some((context, pipeline.subpass().clone()))
+            })
+            .collect();
+        let mut context_pipelines = Vec::with_capacity(subpasses.len());
+        for (context, subpass) in subpasses {
+            context_pipelines.push((context, self.pipeline_for(subpass)?));
+        }
+
+        self.pipeline = pipeline;
+        for (context, pipeline) in context_pipelines {
+            if let Some(state) = self.inactive_contexts.get_mut(&context) {
+                state.pipeline = Some(pipeline);
+            }
+        }
+        Ok(())
+    }
+}
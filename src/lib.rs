@@ -1,74 +1,162 @@
 //! [egui](https://docs.rs/egui) rendering backend for [Vulkano](https://docs.rs/vulkano).
 #![warn(missing_docs)]
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
-use std::sync::Arc;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::{Arc, Weak};
 
-use egui::epaint::{textures::TexturesDelta, ClippedMesh, ClippedShape, ImageData, ImageDelta};
-use egui::{Color32, Context, Rect, TextureId};
-use vulkano::buffer::{BufferAccess, BufferSlice, BufferUsage, CpuAccessibleBuffer};
+use bytemuck::{Pod, Zeroable};
+use egui::color::{gamma_u8_from_linear_f32, linear_f32_from_gamma_u8, linear_u8_from_linear_f32};
+use egui::epaint::{
+    textures::TexturesDelta, ClippedMesh, ClippedShape, ImageData, ImageDelta, TessellationOptions,
+};
+use egui::{Context, Rect, TextureId};
+use vulkano::buffer::cpu_pool::CpuBufferPoolChunk;
+use vulkano::buffer::{
+    BufferAccess, BufferSlice, BufferUsage, CpuAccessibleBuffer, CpuBufferPool, DeviceLocalBuffer,
+    TypedBufferAccess,
+};
 use vulkano::command_buffer::SubpassContents::Inline;
 use vulkano::command_buffer::{
-    AutoCommandBufferBuilder, AutoCommandBufferBuilderContextError, CopyBufferImageError,
-    DrawIndexedError, PrimaryAutoCommandBuffer,
+    AutoCommandBufferBuilder, AutoCommandBufferBuilderContextError, BeginRenderPassError,
+    BuildError, CopyBufferError, CopyBufferImageError, CopyImageError, DrawIndexedError,
+    PrimaryAutoCommandBuffer,
 };
+use vulkano::descriptor_set::layout::{DescriptorSetDesc, DescriptorSetLayout};
 use vulkano::descriptor_set::{
-    DescriptorSetCreationError, PersistentDescriptorSet, WriteDescriptorSet,
+    DescriptorSet, DescriptorSetCreationError, DescriptorSetWithOffsets, PersistentDescriptorSet,
+    WriteDescriptorSet,
 };
-use vulkano::device::{Device, Queue};
-use vulkano::format::Format;
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::{Device, DeviceExtensions, Features, Queue};
+use vulkano::format::{Format, NumericType};
 use vulkano::image::{
-    ImageCreateFlags, ImageCreationError, ImageDimensions, ImageUsage, StorageImage,
+    ImageAccess, ImageCreateFlags, ImageCreationError, ImageDimensions, ImageLayout, ImageUsage,
+    ImmutableImage, MipmapsCount, SampleCount, StorageImage,
 };
-use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, BlendFactor, ColorBlendState};
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
-use vulkano::pipeline::graphics::rasterization::{CullMode, RasterizationState};
-use vulkano::pipeline::graphics::viewport::{Scissor, ViewportState};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendState,
+};
+use vulkano::pipeline::graphics::input_assembly::{IndexType, InputAssemblyState};
+use vulkano::pipeline::graphics::rasterization::{CullMode, PolygonMode, RasterizationState};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport, ViewportState};
 use vulkano::pipeline::graphics::{GraphicsPipeline, GraphicsPipelineCreationError};
-use vulkano::pipeline::Pipeline;
-use vulkano::pipeline::PipelineBindPoint;
+use vulkano::pipeline::{Pipeline, PipelineBindPoint, StateMode};
 use vulkano::sampler::{
     Filter, Sampler, SamplerAddressMode, SamplerCreationError, SamplerMipmapMode,
 };
+use vulkano::shader::ShaderCreationError;
+use vulkano::swapchain::SurfaceTransform;
+use vulkano::{DeviceSize, OomError};
 
+mod bindless;
+mod cached;
+mod custom_shaders;
+mod debug;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+mod offscreen;
+mod push_descriptors;
 mod shaders;
 
-#[derive(Default, Debug, Clone)]
+pub use custom_shaders::{CustomShaders, UserDescriptorSetFn};
+#[cfg(feature = "hot-reload")]
+pub use hot_reload::ShaderReloadError;
+pub use offscreen::RenderToImageError;
+
+/// Device extensions the painter needs, to be enabled when creating the [`Device`].
+/// Currently none are needed in any configuration,
+/// see [`PainterBuilder::required_device_extensions`] for those of optional settings.
+pub fn required_device_extensions() -> DeviceExtensions {
+    DeviceExtensions::none()
+}
+
+/// Device extensions that optional settings use if they are enabled on the [`Device`],
+/// and fall back gracefully without:
+/// * `khr_push_descriptor` for [`PainterBuilder::use_push_descriptors`]
+///
+/// Enable the ones your physical device supports,
+/// e.g. with `physical.supported_extensions().intersection(&optional_device_extensions())`.
+pub fn optional_device_extensions() -> DeviceExtensions {
+    DeviceExtensions {
+        khr_push_descriptor: true,
+        ..DeviceExtensions::none()
+    }
+}
+
+/// Device features the painter needs in every configuration,
+/// to be enabled when creating the [`Device`].
+/// The pipeline only uses state every device supports, e.g. lines of width 1,
+/// so this is empty; multiview subpasses need `multiview` like any other use of them.
+/// See [`PainterBuilder::required_features`] for the features of a configuration.
+pub fn required_features() -> Features {
+    Features::none()
+}
+
+/// Device features that optional settings use if they are enabled on the [`Device`],
+/// and fall back gracefully without:
+/// * `sampler_anisotropy` for [`TextureOptions::anisotropy`]
+/// * `shader_sampled_image_array_dynamic_indexing` for [`PainterBuilder::bindless_textures`]
+/// * `fill_mode_non_solid` for [`Painter::set_wireframe`]
+///
+/// Enable the ones your physical device supports,
+/// e.g. with `physical.supported_features().intersection(&optional_features())`.
+pub fn optional_features() -> Features {
+    Features {
+        sampler_anisotropy: true,
+        shader_sampled_image_array_dynamic_indexing: true,
+        fill_mode_non_solid: true,
+        ..Features::none()
+    }
+}
+
+/// Returns the largest width or height of a texture the physical device supports,
+/// e.g. to create an `egui_winit::State` before the painter exists.
+/// See [`Painter::max_texture_side`].
+pub fn max_texture_side(physical: &PhysicalDevice) -> usize {
+    physical.properties().max_image_dimension2_d as usize
+}
+
+/// Vertex with the same memory layout as [`egui::epaint::Vertex`],
+/// so meshes can be copied into the vertex buffer without converting each vertex.
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy, Pod, Zeroable)]
 struct Vertex {
     pub pos: [f32; 2],
     pub uv: [f32; 2],
-    pub color: [f32; 4],
-}
-
-impl From<&egui::epaint::Vertex> for Vertex {
-    fn from(v: &egui::epaint::Vertex) -> Self {
-        let convert = {
-            |c: Color32| {
-                [
-                    c.r() as f32 / 255.0,
-                    c.g() as f32 / 255.0,
-                    c.b() as f32 / 255.0,
-                    c.a() as f32 / 255.0,
-                ]
-            }
-        };
-
-        Self {
-            pos: [v.pos.x, v.pos.y],
-            uv: [v.uv.x, v.uv.y],
-            color: convert(v.color),
-        }
-    }
+    /// Premultiplied sRGBA color with one byte per channel, unpacked in the vertex shader
+    pub color: u32,
 }
 
 vulkano::impl_vertex!(Vertex, pos, uv, color);
 
+// Meshes are cast to `Vertex` slices, which needs both types to have the same size
+const _: () = assert!(
+    std::mem::size_of::<Vertex>() == std::mem::size_of::<egui::epaint::Vertex>()
+        && std::mem::align_of::<Vertex>() == std::mem::align_of::<egui::epaint::Vertex>()
+);
+
+/// Transform of the gui's clip space positions when drawing without one
+const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
 use thiserror::Error;
 use vulkano::command_buffer::pool::CommandPoolBuilderAlloc;
-use vulkano::image::view::{ImageView, ImageViewCreationError};
+use vulkano::image::view::{ImageView, ImageViewAbstract, ImageViewCreationError, ImageViewType};
+use vulkano::memory::pool::StdMemoryPool;
 use vulkano::memory::DeviceMemoryAllocError;
 use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
-use vulkano::render_pass::Subpass;
+use vulkano::render_pass::{Framebuffer, RenderPass, RenderPassCreationError, Subpass};
+
+use crate::bindless::BindlessTextures;
+use crate::custom_shaders::ShaderModules;
+use crate::push_descriptors::PushedTexture;
 
 #[derive(Error, Debug)]
 pub enum PainterCreationError {
@@ -76,6 +164,44 @@ pub enum PainterCreationError {
     CreatePipelineFailed(#[from] GraphicsPipelineCreationError),
     #[error(transparent)]
     CreateSamplerFailed(#[from] SamplerCreationError),
+    #[error(transparent)]
+    CreateShaderFailed(#[from] ShaderCreationError),
+    /// The subpass can't be used to draw the gui, e.g. because it only has a depth attachment.
+    /// Checked before creating the pipeline, which would fail with a less helpful error.
+    #[error("incompatible subpass: expected {expected}, found {found}")]
+    IncompatibleSubpass {
+        /// What the gui needs from the subpass
+        expected: String,
+        /// What the subpass has instead
+        found: String,
+    },
+    /// A pipeline passed to [`Painter::with_pipeline`] or [`Painter::from_parts`] can't draw the
+    /// painter's geometry, e.g. because its vertex input doesn't match the painter's vertices.
+    /// Create it with [`create_pipeline`] to get one that can.
+    #[error("incompatible pipeline: expected {expected}, found {found}")]
+    IncompatiblePipeline {
+        /// What the painter needs from the pipeline
+        expected: String,
+        /// What the pipeline has instead
+        found: String,
+    },
+    /// A shader passed to [`PainterBuilder::custom_shaders`] doesn't fit the painter,
+    /// e.g. because it uses descriptors outside of set 1
+    /// or its inputs don't match the outputs of the vertex shader.
+    #[error("incompatible {stage} shader: {reason}")]
+    IncompatibleShader {
+        /// `"vertex"` or `"fragment"`
+        stage: &'static str,
+        /// What doesn't fit, from the reflection of the shader
+        reason: String,
+    },
+    /// The subpass needs a device feature that isn't enabled, e.g. `multiview` for a subpass
+    /// with a view mask. The pipeline itself needs no features.
+    #[error("the `{feature}` feature has to be enabled on the device")]
+    MissingFeature {
+        /// Name of the feature in [`Features`]
+        feature: &'static str,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -83,13 +209,37 @@ pub enum UpdateTexturesError {
     #[error(transparent)]
     CreateImageViewFailed(#[from] ImageViewCreationError),
     #[error(transparent)]
+    CreateSamplerFailed(#[from] SamplerCreationError),
+    #[error(transparent)]
     BuildFailed(#[from] DescriptorSetCreationError),
     #[error(transparent)]
     Alloc(#[from] DeviceMemoryAllocError),
     #[error(transparent)]
     Copy(#[from] CopyBufferImageError),
     #[error(transparent)]
+    CopyImage(#[from] CopyImageError),
+    #[error(transparent)]
     CreateImage(#[from] ImageCreationError),
+    /// egui sent a texture larger than the device supports,
+    /// see [`Painter::max_texture_side`].
+    #[error("texture {texture_id:?} is {size:?} pixels, larger than the maximum side of {max_texture_side}")]
+    TextureTooLarge {
+        /// Id of the texture
+        texture_id: TextureId,
+        /// Width and height of the texture
+        size: [usize; 2],
+        /// Largest width or height the device supports
+        max_texture_side: usize,
+    },
+    /// A view registered with [`Painter::register_user_image`] isn't a single layer 2D view,
+    /// which is the only kind the gui's `sampler2D` can sample.
+    #[error("user images have to be 2D views of a single layer, got a {view_type:?} view of {array_layers} layers")]
+    UnsupportedImageView {
+        /// Type of the registered view
+        view_type: ImageViewType,
+        /// Number of array layers of the registered view
+        array_layers: u32,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -102,331 +252,3888 @@ pub enum DrawError {
     CreateBuffersFailed(#[from] DeviceMemoryAllocError),
     #[error(transparent)]
     DrawIndexedFailed(#[from] DrawIndexedError),
+    /// Copying geometry into device local buffers failed.
+    /// This happens when geometry is uploaded inside a render pass,
+    /// see [`PainterBuilder::device_local_geometry`].
+    #[error(transparent)]
+    CopyGeometryFailed(#[from] CopyBufferError),
+    /// Creating the pipeline for bindless textures failed,
+    /// see [`PainterBuilder::bindless_textures`].
+    #[error(transparent)]
+    CreatePipelineFailed(#[from] GraphicsPipelineCreationError),
+    /// The shapes use a texture of a context that [`Painter::update_textures`] was never called
+    /// for, e.g. because the call is missing or its error was ignored.
+    /// Draws that only use images registered with [`Painter::register_user_image`] don't need it.
+    #[error("texture {texture_id:?} was never uploaded: call Painter::update_textures with the textures_delta of every frame before drawing its shapes")]
+    TexturesNotInitialized {
+        /// The first texture that is missing
+        texture_id: TextureId,
+    },
+    /// A mesh has an index past the end of its vertices.
+    /// Only checked with the `validate-meshes` feature.
+    #[error(
+        "mesh with texture {texture_id:?} has index {index}, but only {vertex_count} vertices"
+    )]
+    InvalidMesh {
+        /// Texture of the mesh
+        texture_id: TextureId,
+        /// The first index that is out of range
+        index: u32,
+        /// Number of vertices in the mesh
+        vertex_count: usize,
+    },
+    /// A mesh has a vertex whose position isn't finite, e.g. NaN.
+    /// Only checked with the `validate-meshes` feature.
+    #[error("mesh with texture {texture_id:?} has a vertex {vertex} that isn't finite")]
+    NonFiniteVertex {
+        /// Texture of the mesh
+        texture_id: TextureId,
+        /// Index of the first vertex that isn't finite
+        vertex: usize,
+    },
+    /// Creating the secondary command buffer of [`Painter::draw_cached`] failed.
+    #[error(transparent)]
+    CreateCommandBufferFailed(#[from] OomError),
+    /// Building the secondary command buffer of [`Painter::draw_cached`] failed.
+    #[error(transparent)]
+    BuildCommandBufferFailed(#[from] BuildError),
+}
+
+/// Error returned by [`Painter::draw_full_frame`].
+#[derive(Error, Debug)]
+pub enum DrawFullFrameError {
+    #[error(transparent)]
+    CreatePipelineFailed(#[from] PainterCreationError),
+    #[error(transparent)]
+    BeginRenderPassFailed(#[from] BeginRenderPassError),
+    #[error(transparent)]
+    DrawFailed(#[from] DrawError),
+    #[error(transparent)]
+    EndRenderPassFailed(#[from] AutoCommandBufferBuilderContextError),
 }
 
-#[must_use = "You must use this to avoid attempting to modify a texture that's still in use"]
-#[derive(PartialEq)]
-/// You must use this to avoid attempting to modify a texture that's still in use.
-pub enum UpdateTexturesResult {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// The most significant kind of change made by [`Painter::update_textures`].
+///
+/// Textures that might still be in use are never modified,
+/// but retired until [`Painter::collect_garbage`] drops them, so none of these require waiting.
+pub enum TextureChange {
     /// No texture will be modified in this frame.
     Unchanged,
-    /// A texture will be modified in this frame,
-    /// and you must wait for the last frame to finish before submitting the next command buffer.
-    Changed,
+    /// Only regions of existing textures were updated, e.g. glyphs added to the font atlas.
+    Updated,
+    /// A texture was created or recreated from scratch, e.g. because the font atlas grew.
+    Reallocated,
 }
 
-/// Contains everything needed to render the gui.
-pub struct Painter {
-    device: Arc<Device>,
-    queue: Arc<Queue>,
-    /// Graphics pipeline used to render the gui.
-    pub pipeline: Arc<GraphicsPipeline>,
-    /// Texture sampler used to render the gui.
-    pub sampler: Arc<Sampler>,
-    images: HashMap<egui::TextureId, Arc<StorageImage>>,
-    texture_sets: HashMap<egui::TextureId, Arc<PersistentDescriptorSet>>,
-    texture_free_queue: Vec<egui::TextureId>,
+impl Default for TextureChange {
+    fn default() -> Self {
+        Self::Unchanged
+    }
 }
 
-impl Painter {
-    /// Pass in the vulkano [`Device`], [`Queue`] and [`Subpass`]
-    /// that you want to use to render the gui.
-    pub fn new(
-        device: Arc<Device>,
-        queue: Arc<Queue>,
-        subpass: Subpass,
-    ) -> Result<Self, PainterCreationError> {
-        let pipeline = create_pipeline(device.clone(), subpass.clone())?;
-        let sampler = create_sampler(device.clone())?;
-        Ok(Self {
-            device,
-            queue,
-            pipeline,
-            sampler,
-            images: Default::default(),
-            texture_sets: Default::default(),
-            texture_free_queue: Vec::new(),
+/// Tells you which textures were changed by [`Painter::update_textures`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateTexturesResult {
+    /// Textures that were created or updated, sorted by their id,
+    /// since the [`TexturesDelta`] stores them in a hash map without an order
+    pub set: Vec<TextureId>,
+    /// Textures that egui freed, their images are released after the next draw
+    pub freed: Vec<TextureId>,
+    /// Whether the font atlas was created or updated
+    pub font_atlas_changed: bool,
+    /// The most significant change of all textures in [`UpdateTexturesResult::set`]
+    pub change: TextureChange,
+}
+
+impl UpdateTexturesResult {
+    /// Returns true if any texture was created or updated.
+    pub fn changed(&self) -> bool {
+        self.change != TextureChange::Unchanged
+    }
+
+    /// Records a change of a texture
+    fn push(&mut self, texture_id: TextureId, change: TextureChange) {
+        self.set.push(texture_id);
+        self.font_atlas_changed |= texture_id == TextureId::default();
+        self.change = self.change.max(change);
+    }
+}
+
+/// Image of a texture, mipmapped and user textures can't be modified after they were created.
+#[derive(Clone)]
+enum TextureImage {
+    Storage(Arc<StorageImage>),
+    /// Image with a full mip chain and its RGBA pixels, which partial updates are applied to
+    Mipmapped(Arc<ImmutableImage>, Arc<Vec<u8>>),
+    /// Image view registered with [`Painter::register_user_image`]
+    User(Arc<dyn ImageViewAbstract>),
+}
+
+impl TextureImage {
+    /// Create an image view to sample the whole image
+    fn view(&self) -> Result<Arc<dyn ImageViewAbstract>, ImageViewCreationError> {
+        Ok(match self {
+            TextureImage::Storage(image) => ImageView::new(image.clone())?,
+            TextureImage::Mipmapped(image, _) => ImageView::new(image.clone())?,
+            TextureImage::User(view) => view.clone(),
         })
     }
+}
 
-    fn write_image_delta<P>(
-        &mut self,
-        image: Arc<StorageImage>,
-        delta: &ImageDelta,
-        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
-    ) -> Result<(), UpdateTexturesError>
-    where
-        P: CommandPoolBuilderAlloc,
-    {
-        let image_data = match &delta.image {
-            ImageData::Color(image) => image
-                .pixels
-                .iter()
-                .flat_map(|c| c.to_array())
-                .collect::<Vec<_>>(),
-            ImageData::Alpha(image) => image
-                .pixels
-                .iter()
-                .flat_map(|&r| vec![r, r, r, r])
-                .collect::<Vec<_>>(),
-        };
-        let img_buffer = CpuAccessibleBuffer::from_iter(
-            self.device.clone(),
-            BufferUsage::transfer_source(),
-            false,
-            image_data,
-        )?;
+/// A texture that was replaced or freed while the GPU might still be using it.
+struct RetiredTexture {
+    image: Option<TextureImage>,
+    set: Option<Arc<PersistentDescriptorSet>>,
+}
 
-        let size = [delta.image.width() as u32, delta.image.height() as u32, 1];
-        let offset = match delta.pos {
-            None => [0, 0, 0],
-            Some(pos) => [pos[0] as u32, pos[1] as u32, 0],
-        };
+/// The last full upload of a texture, to skip uploading the same pixels again.
+/// The hash rejects most changed uploads cheaply, the pixels are compared only if it matches.
+struct LastUpload {
+    hash: u64,
+    options: TextureOptions,
+    image: ImageData,
+}
 
-        builder.copy_buffer_to_image_dimensions(img_buffer, image, offset, size, 0, 1, 0)?;
-        Ok(())
+impl LastUpload {
+    /// Whether an upload of the image with the options would give the texture the same pixels
+    fn matches(&self, hash: u64, image: &ImageData, options: TextureOptions) -> bool {
+        self.hash == hash && self.options == options && self.image == *image
     }
+}
 
-    /// Uploads all newly created and modified textures to the GPU.
-    /// Has to be called before entering the first render pass.  
-    /// If the return value is [`UpdateTexturesResult::Changed`],
-    /// a texture will be changed in this frame and you need to wait for the last frame to finish
-    /// before submitting the command buffer for this frame.
-    pub fn update_textures<P>(
-        &mut self,
-        textures_delta: TexturesDelta,
-        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
-    ) -> Result<UpdateTexturesResult, UpdateTexturesError>
-    where
-        P: CommandPoolBuilderAlloc,
-    {
-        for texture_id in textures_delta.free {
-            self.texture_free_queue.push(texture_id);
-        }
+/// Alignment of the pixels of each upload in the staging buffer, the size of an RGBA texel
+const STAGING_ALIGNMENT: usize = 4;
 
-        let mut result = UpdateTexturesResult::Unchanged;
+/// Pixels staged by [`Painter::update_textures`] to be copied into an image
+struct PendingUpload {
+    image: Arc<dyn ImageAccess>,
+    pixels: Vec<u8>,
+    offset: [u32; 3],
+    size: [u32; 3],
+    mip_level: u32,
+}
 
-        for (texture_id, delta) in &textures_delta.set {
-            let image = if delta.is_whole() {
-                let image = create_image(self.queue.clone(), &delta.image)?;
-                let layout = &self.pipeline.layout().descriptor_set_layouts()[0];
+/// Vertex buffer of a frame, either host visible or a device local copy of one.
+#[derive(Clone)]
+enum VertexBuffer {
+    Host(Arc<CpuAccessibleBuffer<[Vertex]>>),
+    Pooled(Arc<CpuBufferPoolChunk<Vertex, Arc<StdMemoryPool>>>),
+    DeviceLocal(Arc<DeviceLocalBuffer<[Vertex]>>),
+}
 
-                let set = PersistentDescriptorSet::new(
-                    layout.clone(),
-                    [WriteDescriptorSet::image_view_sampler(
-                        0,
-                        ImageView::new(image.clone())?,
-                        self.sampler.clone(),
-                    )],
-                )?;
+impl VertexBuffer {
+    /// The vertices in `range` as an untyped buffer
+    fn slice(&self, range: Range<DeviceSize>) -> Arc<dyn BufferAccess> {
+        match self {
+            VertexBuffer::Host(buffer) => typed_slice(buffer, range),
+            VertexBuffer::Pooled(buffer) => typed_slice(buffer, range),
+            VertexBuffer::DeviceLocal(buffer) => typed_slice(buffer, range),
+        }
+    }
+}
 
-                self.texture_sets.insert(*texture_id, set);
-                self.images.insert(*texture_id, image.clone());
-                image
-            } else {
-                result = UpdateTexturesResult::Changed; //modifying an existing image that might be in use
-                self.images[texture_id].clone()
-            };
-            self.write_image_delta(image, delta, builder)?;
+/// Index buffer using the smallest index type that fits all indices of a frame,
+/// either host visible or a device local copy of one.
+#[derive(Clone)]
+enum IndexBuffer {
+    U16(Arc<CpuAccessibleBuffer<[u16]>>),
+    U32(Arc<CpuAccessibleBuffer<[u32]>>),
+    PooledU16(Arc<CpuBufferPoolChunk<u16, Arc<StdMemoryPool>>>),
+    PooledU32(Arc<CpuBufferPoolChunk<u32, Arc<StdMemoryPool>>>),
+    DeviceLocalU16(Arc<DeviceLocalBuffer<[u16]>>),
+    DeviceLocalU32(Arc<DeviceLocalBuffer<[u32]>>),
+}
+
+impl IndexBuffer {
+    /// Allocate an index buffer with room for `capacity` indices and fill it with the given indices
+    fn new(
+        device: Arc<Device>,
+        indices: &[u32],
+        capacity: usize,
+        use_u16: bool,
+    ) -> Result<Self, DeviceMemoryAllocError> {
+        if use_u16 {
+            let buffer = CpuAccessibleBuffer::from_iter(
+                device,
+                staging_usage(BufferUsage::index_buffer()),
+                false,
+                indices
+                    .iter()
+                    .map(|&i| i as u16)
+                    .chain(std::iter::repeat(0))
+                    .take(capacity),
+            )?;
+            Ok(IndexBuffer::U16(buffer))
+        } else {
+            let buffer = CpuAccessibleBuffer::from_iter(
+                device,
+                staging_usage(BufferUsage::index_buffer()),
+                false,
+                indices
+                    .iter()
+                    .copied()
+                    .chain(std::iter::repeat(0))
+                    .take(capacity),
+            )?;
+            Ok(IndexBuffer::U32(buffer))
         }
+    }
 
-        Ok(result)
+    /// Allocate a device local index buffer with room for `capacity` indices
+    fn new_device_local(
+        queue: &Arc<Queue>,
+        capacity: usize,
+        use_u16: bool,
+    ) -> Result<Self, DeviceMemoryAllocError> {
+        let usage = BufferUsage {
+            index_buffer: true,
+            transfer_destination: true,
+            ..BufferUsage::none()
+        };
+        let device = queue.device().clone();
+        if use_u16 {
+            let buffer = DeviceLocalBuffer::array(device, capacity as _, usage, [queue.family()])?;
+            Ok(IndexBuffer::DeviceLocalU16(buffer))
+        } else {
+            let buffer = DeviceLocalBuffer::array(device, capacity as _, usage, [queue.family()])?;
+            Ok(IndexBuffer::DeviceLocalU32(buffer))
+        }
     }
 
-    /// Free textures freed by egui, *after* drawing
-    fn free_textures(&mut self) {
-        for texture_id in &self.texture_free_queue {
-            self.texture_sets.remove(texture_id);
-            self.images.remove(texture_id);
+    fn is_u16(&self) -> bool {
+        matches!(
+            self,
+            IndexBuffer::U16(_) | IndexBuffer::PooledU16(_) | IndexBuffer::DeviceLocalU16(_)
+        )
+    }
+
+    /// The indices in `range` as an untyped buffer
+    fn slice(&self, range: Range<DeviceSize>) -> Arc<dyn BufferAccess> {
+        match self {
+            IndexBuffer::U16(buffer) => typed_slice(buffer, range),
+            IndexBuffer::U32(buffer) => typed_slice(buffer, range),
+            IndexBuffer::PooledU16(buffer) => typed_slice(buffer, range),
+            IndexBuffer::PooledU32(buffer) => typed_slice(buffer, range),
+            IndexBuffer::DeviceLocalU16(buffer) => typed_slice(buffer, range),
+            IndexBuffer::DeviceLocalU32(buffer) => typed_slice(buffer, range),
         }
+    }
 
-        self.texture_free_queue.clear();
+    /// Overwrite the start of the buffer with the given indices.
+    /// Returns false if the buffer is still in use by the GPU, pooled or isn't host visible.
+    fn write(&self, indices: &[u32]) -> bool {
+        match self {
+            IndexBuffer::U16(buffer) => match buffer.write() {
+                Ok(mut content) => {
+                    for (dst, &src) in content.iter_mut().zip(indices) {
+                        *dst = src as u16;
+                    }
+                    true
+                }
+                Err(_) => false,
+            },
+            IndexBuffer::U32(buffer) => match buffer.write() {
+                Ok(mut content) => {
+                    content[..indices.len()].copy_from_slice(indices);
+                    true
+                }
+                Err(_) => false,
+            },
+            _ => false,
+        }
     }
+}
 
-    /// Advances to the next rendering subpass and uses the [`ClippedShape`]s from [`egui::FullOutput`] to draw the gui.
-    pub fn draw<P>(
-        &mut self,
-        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
-        window_size_points: [f32; 2],
-        egui_ctx: &Context,
-        clipped_shapes: Vec<ClippedShape>,
-    ) -> Result<(), DrawError>
-    where
-        P: CommandPoolBuilderAlloc,
-    {
-        builder
-            .next_subpass(Inline)?
-            .bind_pipeline_graphics(self.pipeline.clone());
+/// Vertex and index buffers that are reused by later frames.
+struct GeometryBuffers {
+    vertices: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    indices: IndexBuffer,
+    vertex_capacity: usize,
+    index_capacity: usize,
+    /// Device local buffers of the same size that the geometry is copied into,
+    /// created on first use if device local geometry is enabled
+    device_local: Option<(Arc<DeviceLocalBuffer<[Vertex]>>, IndexBuffer)>,
+}
 
-        let clipped_meshes: Vec<ClippedMesh> = egui_ctx.tessellate(clipped_shapes);
-        let num_meshes = clipped_meshes.len();
+impl GeometryBuffers {
+    /// Allocate buffers with some room to grow and fill them with the given geometry
+    fn new(
+        device: Arc<Device>,
+        vertices: &[Vertex],
+        indices: &[u32],
+        use_u16: bool,
+    ) -> Result<Self, DeviceMemoryAllocError> {
+        let vertex_capacity = vertices.len().next_power_of_two();
+        let index_capacity = indices.len().next_power_of_two();
 
-        let mut verts = Vec::<Vertex>::with_capacity(num_meshes * 4);
-        let mut indices = Vec::<u32>::with_capacity(num_meshes * 6);
-        let mut clips = Vec::<Rect>::with_capacity(num_meshes);
-        let mut texture_ids = Vec::<TextureId>::with_capacity(num_meshes);
-        let mut offsets = Vec::<(usize, usize)>::with_capacity(num_meshes);
+        let vertices = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            staging_usage(BufferUsage::vertex_buffer()),
+            false,
+            vertices
+                .iter()
+                .cloned()
+                .chain(std::iter::repeat(Vertex::default()))
+                .take(vertex_capacity),
+        )?;
+        let indices = IndexBuffer::new(device, indices, index_capacity, use_u16)?;
 
-        for cm in clipped_meshes.iter() {
-            let (clip, mesh) = (cm.0, &cm.1);
+        Ok(Self {
+            vertices,
+            indices,
+            vertex_capacity,
+            index_capacity,
+            device_local: None,
+        })
+    }
 
-            // Skip empty meshes
-            if mesh.vertices.len() == 0 || mesh.indices.len() == 0 {
-                continue;
-            }
+    /// Overwrite the buffers with the given geometry.
+    /// Returns false if they are too small, have the wrong index type or are still in use by the GPU.
+    fn write(&self, vertices: &[Vertex], indices: &[u32], use_u16: bool) -> bool {
+        if self.vertex_capacity < vertices.len()
+            || self.index_capacity < indices.len()
+            || self.indices.is_u16() != use_u16
+        {
+            return false;
+        }
 
-            offsets.push((verts.len(), indices.len()));
-            texture_ids.push(mesh.texture_id);
+        let mut vertex_content = match self.vertices.write() {
+            Ok(content) => content,
+            Err(_) => return false,
+        };
+        if !self.indices.write(indices) {
+            return false;
+        }
+        vertex_content[..vertices.len()].clone_from_slice(vertices);
+        true
+    }
+}
 
-            for v in mesh.vertices.iter() {
-                verts.push(v.into());
-            }
+/// How a texture is sampled outside of the `0..1` UV range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureWrapMode {
+    /// Repeat the edge pixels. This is what egui expects for the font atlas.
+    ClampToEdge,
+    /// Tile the texture.
+    Repeat,
+    /// Tile the texture, mirroring every other tile.
+    MirroredRepeat,
+}
 
-            for i in mesh.indices.iter() {
-                indices.push(*i);
-            }
+impl Default for TextureWrapMode {
+    fn default() -> Self {
+        Self::ClampToEdge
+    }
+}
 
-            clips.push(clip);
+impl From<TextureWrapMode> for SamplerAddressMode {
+    fn from(mode: TextureWrapMode) -> Self {
+        match mode {
+            TextureWrapMode::ClampToEdge => SamplerAddressMode::ClampToEdge,
+            TextureWrapMode::Repeat => SamplerAddressMode::Repeat,
+            TextureWrapMode::MirroredRepeat => SamplerAddressMode::MirroredRepeat,
         }
-        offsets.push((verts.len(), indices.len()));
+    }
+}
 
-        // Return if there's nothing to render
-        if clips.len() == 0 {
-            return Ok(());
-        }
+/// Options controlling how a texture is sampled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextureOptions {
+    /// Wrap mode for the horizontal texture coordinate.
+    pub wrap_u: TextureWrapMode,
+    /// Wrap mode for the vertical texture coordinate.
+    pub wrap_v: TextureWrapMode,
+    /// Maximum level of anisotropic filtering, clamped to the limit of the device.
+    /// Ignored unless the `sampler_anisotropy` feature is enabled on the device.
+    pub anisotropy: Option<u8>,
+    /// Create the texture with a full mip chain, generated when it is uploaded,
+    /// so it doesn't shimmer when drawn smaller than its size.
+    /// Only applies to the next full upload of the texture.
+    /// For views registered with [`Painter::register_user_image`],
+    /// this samples all mip levels of the view instead of only the first one.
+    ///
+    /// The mips are generated on the CPU and uploaded with the texture.
+    /// The painter keeps a copy of their pixels, so partial updates can regenerate the mips.
+    pub mipmaps: bool,
+    /// Added to the level of detail the GPU computes, clamped to the limit of the device.
+    /// Negative values pick larger mip levels, e.g. `-1.0` for sharper small text at fractional
+    /// `pixels_per_point`, at the cost of more aliasing. Defaults to `0.0`.
+    /// Only has an effect with [`TextureOptions::mipmaps`],
+    /// without them only the base level is sampled anyway.
+    pub lod_bias: f32,
+}
 
-        let (vertex_buf, index_buf) = self.create_buffers((verts, indices))?;
-        for (idx, clip) in clips.iter().enumerate() {
-            let mut scissors = Vec::with_capacity(1);
-            let o = clip.min;
-            let (w, h) = (clip.width() as u32, clip.height() as u32);
-            scissors.push(Scissor {
-                origin: [(o.x as u32), (o.y as u32)],
-                dimensions: [w, h],
-            });
-            builder.set_scissor(0, scissors);
+impl TextureOptions {
+    /// The fields with the LOD bias as bits, to compare and hash options
+    /// so they can be used as keys of the sampler cache
+    fn key(&self) -> (TextureWrapMode, TextureWrapMode, Option<u8>, bool, u32) {
+        (
+            self.wrap_u,
+            self.wrap_v,
+            self.anisotropy,
+            self.mipmaps,
+            self.lod_bias.to_bits(),
+        )
+    }
+}
+
+impl PartialEq for TextureOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
 
-            let offset = offsets[idx];
-            let end = offsets[idx + 1];
+impl Eq for TextureOptions {}
 
-            let vb_slice = BufferSlice::from_typed_buffer_access(vertex_buf.clone())
-                .slice(offset.0 as u64..end.0 as u64)
-                .unwrap();
-            let ib_slice = BufferSlice::from_typed_buffer_access(index_buf.clone())
-                .slice(offset.1 as u64..end.1 as u64)
-                .unwrap();
+impl Hash for TextureOptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
 
-            let texture_set = self.texture_sets.get(&texture_ids[idx]);
-            if texture_set.is_none() {
-                continue; //skip if we don't have a texture
-            }
+/// How the color attachment that the gui is drawn to encodes colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputColorSpace {
+    /// The attachment has an sRGB format (e.g. `B8G8R8A8_SRGB`)
+    /// that encodes the linear shader output in hardware.
+    SrgbAttachment,
+    /// The attachment has a UNORM format (e.g. `B8G8R8A8_UNORM`) but is displayed as sRGB,
+    /// so the shader encodes its output to sRGB.
+    EncodeSrgbInShader,
+    /// The attachment stores linear colors (e.g. `R16G16B16A16_SFLOAT`),
+    /// the shader output is written unchanged.
+    /// For HDR attachments, see [`Painter::set_sdr_white_level`].
+    Linear,
+    /// The attachment has a float format and stores extended linear sRGB (scRGB),
+    /// e.g. an `R16G16B16A16_SFLOAT` swapchain with the `ExtendedSrgbLinear` color space,
+    /// where 1.0 is 80 nits and values above it are brighter.
+    ///
+    /// The shader output is written unchanged and never saturates at 1.0.
+    /// egui's colors are 8 bit, so brightness above SDR white comes from
+    /// [`Painter::set_sdr_white_level`], e.g. 2.5 for 200 nit white.
+    /// Subpasses whose color attachments don't have a float format are rejected with
+    /// [`PainterCreationError::IncompatibleSubpass`], because they would clamp the output.
+    ExtendedSrgbLinear,
+}
 
-            builder
-                .bind_vertex_buffers(0, vb_slice.clone())
-                .bind_index_buffer(ib_slice.clone())
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Graphics,
-                    self.pipeline.layout().clone(),
-                    0,
-                    texture_set.unwrap().clone(),
-                )
-                .push_constants(self.pipeline.layout().clone(), 0, window_size_points)
-                .draw_indexed(ib_slice.len() as u32, 1, 0, 0, 0)?;
+impl Default for OutputColorSpace {
+    fn default() -> Self {
+        Self::SrgbAttachment
+    }
+}
+
+/// Settings of a pipeline created by [`create_pipeline`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// How the color attachment encodes colors, see [`PainterBuilder::color_space`].
+    pub color_space: OutputColorSpace,
+    /// Faces that aren't drawn. Defaults to [`CullMode::None`],
+    /// since egui doesn't wind its triangles consistently.
+    pub cull_mode: CullMode,
+    /// How the gui is blended into the color attachments, see [`PainterBuilder::blend`].
+    /// Defaults to [`premultiplied_alpha_blend`].
+    pub blend: AttachmentBlend,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            color_space: OutputColorSpace::default(),
+            cull_mode: CullMode::None,
+            blend: premultiplied_alpha_blend(),
         }
-        self.free_textures();
-        Ok(())
     }
+}
 
-    /// Create vulkano CpuAccessibleBuffer objects for the vertices and indices
-    fn create_buffers(
-        &self,
-        triangles: (Vec<Vertex>, Vec<u32>),
-    ) -> Result<
-        (
-            Arc<CpuAccessibleBuffer<[Vertex]>>,
-            Arc<CpuAccessibleBuffer<[u32]>>,
-        ),
-        DeviceMemoryAllocError,
-    > {
-        let vertex_buffer = CpuAccessibleBuffer::from_iter(
-            self.device.clone(),
-            BufferUsage::vertex_buffer(),
-            false,
-            triangles.0.iter().cloned(),
-        )?;
+/// Blending of egui's premultiplied colors over the attachment, for both color and alpha:
+/// `One` for the source and `OneMinusSrcAlpha` for the destination.
+///
+/// The alpha of the attachment becomes the coverage of the gui over what was there before,
+/// so an image the gui was drawn into can be composited over another one with the same blending.
+pub fn premultiplied_alpha_blend() -> AttachmentBlend {
+    AttachmentBlend {
+        color_op: BlendOp::Add,
+        color_source: BlendFactor::One,
+        color_destination: BlendFactor::OneMinusSrcAlpha,
+        alpha_op: BlendOp::Add,
+        alpha_source: BlendFactor::One,
+        alpha_destination: BlendFactor::OneMinusSrcAlpha,
+    }
+}
 
-        let index_buffer = CpuAccessibleBuffer::from_iter(
-            self.device.clone(),
-            BufferUsage::index_buffer(),
-            false,
-            triangles.1.iter().cloned(),
-        )?;
+/// Settings of a sampler created by [`create_sampler`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerConfig {
+    /// Wrap modes, filtering and mipmaps of the sampled textures.
+    pub texture_options: TextureOptions,
+    /// Minification and magnification filter, see [`Painter::set_texture_filter`].
+    pub filter: Filter,
+}
 
-        Ok((vertex_buffer, index_buffer))
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            texture_options: TextureOptions::default(),
+            filter: Filter::Linear,
+        }
     }
 }
 
-/// Create a graphics pipeline with the shaders and settings necessary to render egui output
-fn create_pipeline(
-    device: Arc<Device>,
-    subpass: Subpass,
-) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
-    let vs = shaders::vs::load(device.clone()).unwrap();
-    let fs = shaders::fs::load(device.clone()).unwrap();
+/// How the vertex shader interprets vertex colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexColorMode {
+    /// Vertex colors are sRGB, as produced by egui, and are decoded to linear in the shader.
+    Srgb,
+    /// Vertex colors are already linear and are used unchanged.
+    Linear,
+}
 
-    let mut blend = AttachmentBlend::alpha();
-    blend.color_source = BlendFactor::One;
+impl Default for VertexColorMode {
+    fn default() -> Self {
+        Self::Srgb
+    }
+}
 
-    let pipeline = GraphicsPipeline::start()
-        .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
-        .vertex_shader(vs.entry_point("main").unwrap(), ())
-        .input_assembly_state(InputAssemblyState::new())
-        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
-        .fragment_shader(fs.entry_point("main").unwrap(), ())
-        .rasterization_state(RasterizationState::new().cull_mode(CullMode::None))
-        .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend(blend))
-        .render_pass(subpass)
-        .build(device.clone())?;
-    Ok(pipeline)
+/// Position of the gui in one view of a multiview subpass, see [`Painter::set_view_offsets`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewOffset {
+    /// Offset in clip space, where the window spans `-1.0..=1.0`
+    pub offset: [f32; 2],
+    /// Scale in clip space around the center of the view
+    pub scale: [f32; 2],
 }
 
-/// Create a texture sampler for the textures used by egui
-fn create_sampler(device: Arc<Device>) -> Result<Arc<Sampler>, SamplerCreationError> {
-    Sampler::start(device.clone())
-        .mag_filter(Filter::Linear)
-        .min_filter(Filter::Linear)
-        .mipmap_mode(SamplerMipmapMode::Linear)
-        .address_mode_u(SamplerAddressMode::ClampToEdge)
-        .address_mode_v(SamplerAddressMode::ClampToEdge)
-        .address_mode_w(SamplerAddressMode::ClampToEdge)
-        .mip_lod_bias(0.0)
-        .anisotropy(Some(1.0))
-        .min_lod(0.0)
-        .build()
+impl Default for ViewOffset {
+    fn default() -> Self {
+        Self {
+            offset: [0.0, 0.0],
+            scale: [1.0, 1.0],
+        }
+    }
 }
 
-/// Create a Vulkano image for the given egui texture
-fn create_image(
+/// Builder for a [`Painter`] with non-default settings, created by [`Painter::builder`].
+pub struct PainterBuilder {
+    device: Arc<Device>,
     queue: Arc<Queue>,
-    texture: &ImageData,
-) -> Result<Arc<StorageImage>, ImageCreationError> {
-    let dimensions = ImageDimensions::Dim2d {
-        width: texture.width() as u32,
-        height: texture.height() as u32,
-        array_layers: 1,
-    };
+    subpass: Subpass,
+    color_space: OutputColorSpace,
+    device_local_geometry: bool,
+    user_texture_options: TextureOptions,
+    font_mipmaps: bool,
+    bindless_textures: bool,
+    push_descriptors: bool,
+    wireframe: bool,
+    blend: AttachmentBlend,
+    custom_shaders: Option<CustomShaders>,
+    user_descriptor_set: Option<UserDescriptorSetFn>,
+    #[cfg(feature = "hot-reload")]
+    shader_paths: Option<(std::path::PathBuf, std::path::PathBuf)>,
+}
 
-    let format = match texture {
-        ImageData::Color(_) => Format::R8G8B8A8_SRGB,
-        ImageData::Alpha(_) => Format::R8G8B8A8_UNORM,
-    };
+impl PainterBuilder {
+    /// Sets how the color attachment of the subpass encodes colors.
+    /// Defaults to [`OutputColorSpace::SrgbAttachment`].
+    pub fn color_space(mut self, color_space: OutputColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
 
-    let usage = ImageUsage {
-        transfer_destination: true,
-        sampled: true,
-        storage: false,
-        ..ImageUsage::none()
-    };
+    /// Sets the [`TextureOptions`] of all textures except egui's font atlas,
+    /// unless they are overridden with [`Painter::set_texture_options`],
+    /// e.g. to enable anisotropic filtering for every image in an asset browser.
+    /// Defaults to [`TextureOptions::default`].
+    pub fn user_texture_options(mut self, options: TextureOptions) -> Self {
+        self.user_texture_options = options;
+        self
+    }
+
+    /// Sets the wrap mode of both texture coordinates in the
+    /// [`PainterBuilder::user_texture_options`], e.g. [`TextureWrapMode::Repeat`]
+    /// to tile images drawn with UVs outside of `0.0..=1.0`.
+    /// egui's font atlas is always sampled with [`TextureWrapMode::ClampToEdge`].
+    pub fn user_texture_wrap_mode(mut self, mode: TextureWrapMode) -> Self {
+        self.user_texture_options.wrap_u = mode;
+        self.user_texture_options.wrap_v = mode;
+        self
+    }
+
+    /// Sets how the gui is blended into the color attachments,
+    /// e.g. to keep the attachment's alpha when drawing over an opaque scene.
+    /// Defaults to [`premultiplied_alpha_blend`]; egui's colors are premultiplied,
+    /// so the color source factor should stay `One`.
+    pub fn blend(mut self, blend: AttachmentBlend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Creates egui's font atlas with a full mip chain, so text doesn't alias when the gui is
+    /// drawn smaller than its size, e.g. rendered into an image that's shown as a minimap.
+    /// Defaults to `false`.
+    ///
+    /// Text drawn at its size samples the base level and stays as sharp as without mips.
+    /// This is [`TextureOptions::mipmaps`] for the font atlas, so the mips are generated on the
+    /// CPU whenever the atlas changes. [`Painter::set_texture_options`] overrides it.
+    pub fn font_mipmaps(mut self, enabled: bool) -> Self {
+        self.font_mipmaps = enabled;
+        self
+    }
+
+    /// Draw from vertex and index buffers in device local memory instead of host visible memory,
+    /// which is faster to read on discrete GPUs. Defaults to `false`.
+    ///
+    /// The geometry is still written to host visible staging buffers,
+    /// and copying it into the device local buffers has to be recorded outside of a render pass.
+    /// Upload the meshes with [`Painter::upload_meshes`] before beginning the render pass,
+    /// then draw them with [`Painter::draw_uploaded`].
+    /// [`Painter::draw`] and [`Painter::draw_meshes`] return [`DrawError::CopyGeometryFailed`]
+    /// in this mode, because they are called inside the render pass.
+    ///
+    /// The device local buffers are only reused across frames with [`Painter::set_frames_in_flight`].
+    pub fn device_local_geometry(mut self, device_local_geometry: bool) -> Self {
+        self.device_local_geometry = device_local_geometry;
+        self
+    }
+
+    /// Draw all textures from a single descriptor set with an array of textures,
+    /// selecting each draw call's texture with a push constant. Defaults to `false`.
+    ///
+    /// This avoids rebinding a descriptor set whenever the texture changes,
+    /// which helps guis that draw many different images.
+    /// Textures are given a slot when they are uploaded or registered and freeing them
+    /// recycles the slot. The array has 256 slots shared by all contexts,
+    /// textures that don't fit fall back to their own descriptor set.
+    ///
+    /// Needs the `shader_sampled_image_array_dynamic_indexing` feature, see [`optional_features`],
+    /// and device limits that allow 256 sampled images per stage.
+    /// Without them, every texture keeps using its own descriptor set.
+    /// Images rendered with [`Painter::render_to_image`] always use per-texture sets.
+    pub fn bindless_textures(mut self, enabled: bool) -> Self {
+        self.bindless_textures = enabled;
+        self
+    }
+
+    /// Push the texture of each draw call into the command buffer instead of allocating
+    /// a descriptor set for every texture, e.g. for guis that upload and free images all the time.
+    /// Defaults to `false`.
+    ///
+    /// Textures then only keep an image view and a sampler, and no descriptor pool is used.
+    /// Needs the `khr_push_descriptor` extension, see [`optional_device_extensions`].
+    /// Without it, every texture keeps using its own descriptor set.
+    /// [`PainterBuilder::bindless_textures`] is ignored with push descriptors,
+    /// and [`Painter::texture_descriptor_set`] returns `None` for textures that are pushed.
+    pub fn use_push_descriptors(mut self, enabled: bool) -> Self {
+        self.push_descriptors = enabled;
+        self
+    }
+
+    /// Draws wireframes from the first frame on, see [`Painter::set_wireframe`].
+    /// Defaults to `false`.
+    pub fn wireframe(mut self, enabled: bool) -> Self {
+        self.wireframe = enabled;
+        self
+    }
+
+    /// Draws the gui with shaders of your own instead of the painter's,
+    /// e.g. to apply a scanline effect to the gui only.
+    ///
+    /// Shaders that don't fit the painter fail painter creation with
+    /// [`PainterCreationError::IncompatibleShader`], describing the mismatch.
+    /// Descriptors the shaders declare in set 1 are bound from
+    /// [`PainterBuilder::user_descriptor_set`].
+    /// [`PainterBuilder::bindless_textures`] is ignored with custom shaders,
+    /// and [`Painter::render_to_image`] keeps using the painter's shaders.
+    pub fn custom_shaders(mut self, shaders: CustomShaders) -> Self {
+        self.custom_shaders = Some(shaders);
+        self
+    }
+
+    /// Sets the callback that creates the descriptor set bound to set 1 when the custom shaders
+    /// declare one, e.g. with a uniform buffer holding the time of an animated effect.
+    ///
+    /// It's called with the layout of set 1 each time the gui is drawn,
+    /// so it can return a new set for every frame, e.g. with a buffer from a `CpuBufferPool`.
+    pub fn user_descriptor_set<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(Arc<DescriptorSetLayout>) -> Arc<dyn DescriptorSet + Send + Sync> + Send + 'static,
+    {
+        self.user_descriptor_set = Some(Box::new(callback));
+        self
+    }
+
+    /// Watches SPIR-V files of a vertex and a fragment shader, which are loaded as
+    /// [`CustomShaders`] by [`Painter::poll_shader_reload`] whenever either of them changes,
+    /// starting with its first call. Until then the painter draws with the shaders of
+    /// [`PainterBuilder::custom_shaders`], or its own.
+    ///
+    /// Meant for iterating on shaders during development, with the `hot-reload` feature.
+    #[cfg(feature = "hot-reload")]
+    pub fn watch_shaders(
+        mut self,
+        vertex_path: impl Into<std::path::PathBuf>,
+        fragment_path: impl Into<std::path::PathBuf>,
+    ) -> Self {
+        self.shader_paths = Some((vertex_path.into(), fragment_path.into()));
+        self
+    }
+
+    /// Device features this configuration uses, e.g. to check before building which settings
+    /// fall back because the [`Device`] doesn't have them enabled:
+    /// * `sampler_anisotropy` for [`TextureOptions::anisotropy`] of user textures
+    /// * `shader_sampled_image_array_dynamic_indexing` for [`PainterBuilder::bindless_textures`]
+    /// * `fill_mode_non_solid` for [`PainterBuilder::wireframe`]
+    /// * `multiview` for multiview subpasses, without it building fails
+    ///
+    /// Always includes [`required_features`].
+    pub fn required_features(&self) -> Features {
+        let desc = self.subpass.render_pass().desc();
+        let view_mask = desc.subpasses()[self.subpass.index() as usize].view_mask;
+        Features {
+            sampler_anisotropy: self.user_texture_options.anisotropy.is_some(),
+            shader_sampled_image_array_dynamic_indexing: self.bindless_textures
+                && !self.push_descriptors
+                && self.custom_shaders.is_none(),
+            fill_mode_non_solid: self.wireframe,
+            multiview: view_mask != 0,
+            ..Features::none()
+        }
+        .union(&required_features())
+    }
+
+    /// Device extensions this configuration uses, e.g. `khr_push_descriptor` for
+    /// [`PainterBuilder::use_push_descriptors`], see [`PainterBuilder::required_features`].
+    ///
+    /// Always includes [`required_device_extensions`].
+    pub fn required_device_extensions(&self) -> DeviceExtensions {
+        DeviceExtensions {
+            khr_push_descriptor: self.push_descriptors,
+            ..DeviceExtensions::none()
+        }
+        .union(&required_device_extensions())
+    }
+
+    /// Creates the [`Painter`].
+    pub fn build(self) -> Result<Painter, PainterCreationError> {
+        validate_subpass(&self.subpass, self.color_space)?;
+        let config = PipelineConfig {
+            color_space: self.color_space,
+            blend: self.blend,
+            ..PipelineConfig::default()
+        };
+        let shaders = match &self.custom_shaders {
+            Some(shaders) => Some(ShaderModules::load(&self.device, shaders)?),
+            None => None,
+        };
+        let pipeline = create_pipeline_variant(
+            self.device.clone(),
+            self.subpass.clone(),
+            config,
+            PipelineVariant::default(),
+            shaders.as_ref(),
+        )
+        .map_err(custom_shaders::describe_error)?;
+        if shaders.is_some() {
+            validate_pipeline(&pipeline)?;
+        }
+        let sampler = create_sampler(self.device.clone(), SamplerConfig::default())?;
+        self.build_with(pipeline, sampler, shaders)
+    }
+
+    /// Creates the [`Painter`] with the given pipeline and sampler of the default options
+    fn build_with(
+        self,
+        pipeline: Arc<GraphicsPipeline>,
+        sampler: Arc<Sampler>,
+        shaders: Option<ShaderModules>,
+    ) -> Result<Painter, PainterCreationError> {
+        let device = self.device;
+        let push_descriptors = self.push_descriptors && push_descriptors::is_supported(&device);
+        // The texture array needs the painter's own shaders
+        let bindless = if self.bindless_textures
+            && !push_descriptors
+            && shaders.is_none()
+            && bindless::is_supported(&device)
+        {
+            Some(BindlessTextures::default())
+        } else {
+            None
+        };
+        let mut samplers = HashMap::new();
+        samplers.insert(TextureOptions::default(), sampler.clone());
+        Ok(Painter {
+            device: device.clone(),
+            queue: self.queue,
+            pipeline,
+            sampler,
+            samplers,
+            texture_filter: Filter::Linear,
+            images: Default::default(),
+            texture_sets: Default::default(),
+            texture_views: Default::default(),
+            texture_options: Default::default(),
+            texture_free_queue: Vec::new(),
+            pipelines: HashMap::new(),
+            pipeline_variants: HashMap::new(),
+            context: ContextId::default(),
+            inactive_contexts: HashMap::new(),
+            garbage: Vec::new(),
+            spare_images: HashMap::new(),
+            frame: 0,
+            geometry: Vec::new(),
+            next_geometry_slot: 0,
+            vertex_pool: CpuBufferPool::new(
+                device.clone(),
+                staging_usage(BufferUsage::vertex_buffer()),
+            ),
+            index_pool_u16: CpuBufferPool::new(
+                device.clone(),
+                staging_usage(BufferUsage::index_buffer()),
+            ),
+            index_pool_u32: CpuBufferPool::new(
+                device.clone(),
+                staging_usage(BufferUsage::index_buffer()),
+            ),
+            staging_pool: CpuBufferPool::new(device.clone(), BufferUsage::transfer_source()),
+            offscreen: HashMap::new(),
+            intermediate: None,
+            stats: PaintStats::default(),
+            updated_contexts: HashSet::new(),
+            texture_bytes_uploaded: 0,
+            texture_uploads_skipped: 0,
+            last_uploads: HashMap::new(),
+            vertex_color_mode: VertexColorMode::default(),
+            pixel_snapping: false,
+            batch_reordering: false,
+            debug_clip_rects: false,
+            wireframe: self.wireframe && device.enabled_features().fill_mode_non_solid,
+            sdr_white_level: 1.0,
+            time_seconds: 0.0,
+            frame_index: 0,
+            view_offsets: [ViewOffset::default(); 2],
+            pixels_per_point: 1.0,
+            pixels_per_point_override: None,
+            tessellation_options: None,
+            transform: None,
+            surface_transform: SurfaceTransform::Identity,
+            target_viewport: None,
+            ui_transform: None,
+            set_cache: HashMap::new(),
+            next_user_texture: 0,
+            font_texture_alias: None,
+            font_set_override: None,
+            device_local_geometry: self.device_local_geometry,
+            color_space: self.color_space,
+            user_texture_options: self.user_texture_options,
+            font_mipmaps: self.font_mipmaps,
+            blend: self.blend,
+            uploaded: None,
+            last_geometry: HashMap::new(),
+            bindless,
+            push_descriptors,
+            cached_draws: HashMap::new(),
+            custom_shaders: shaders,
+            user_descriptor_set: self.user_descriptor_set,
+            #[cfg(feature = "hot-reload")]
+            shader_watch: self
+                .shader_paths
+                .map(|(vertex, fragment)| hot_reload::ShaderWatch::new(vertex, fragment)),
+        })
+    }
+}
+
+/// Identifies one of several egui contexts that are drawn by the same [`Painter`],
+/// see [`Painter::select_context`]. Contexts use the default id unless selected otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ContextId(pub u64);
+
+/// Textures and pipeline of a context that is not currently selected.
+#[derive(Default)]
+struct ContextState {
+    /// `None` for contexts that were never selected, which start out with the current pipeline
+    pipeline: Option<Arc<GraphicsPipeline>>,
+    images: HashMap<egui::TextureId, TextureImage>,
+    texture_sets: HashMap<egui::TextureId, Arc<PersistentDescriptorSet>>,
+    texture_views: HashMap<egui::TextureId, PushedTexture>,
+    texture_options: HashMap<egui::TextureId, TextureOptions>,
+    texture_free_queue: Vec<egui::TextureId>,
+}
+
+/// Settings for egui that match a [`Painter`] and its device, see [`Painter::context_options`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContextOptions {
+    /// Largest width or height of a texture the device supports,
+    /// which limits the size of the font atlas egui builds.
+    pub max_texture_side: usize,
+}
+
+impl ContextOptions {
+    /// Writes the options into the input of a frame, before passing it to egui.
+    pub fn apply(&self, raw_input: &mut egui::RawInput) {
+        raw_input.max_texture_side = Some(self.max_texture_side);
+    }
+}
+
+/// Statistics about the gui most recently drawn by a [`Painter`].
+///
+/// Only draws into your own render passes count, not those of [`Painter::render_to_image`],
+/// [`Painter::draw_to_intermediate`] or [`Painter::draw_cached`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PaintStats {
+    /// Number of meshes produced by tessellation.
+    pub meshes: usize,
+    /// Number of draw calls recorded, after merging adjacent meshes
+    /// that share a clip rect and texture.
+    pub draw_calls: usize,
+    /// Number of vertices written to the vertex buffer.
+    pub vertices: usize,
+    /// Number of indices written to the index buffer.
+    pub indices: usize,
+    /// Number of bytes of texture data uploaded by [`Painter::update_textures`]
+    /// since the previous draw.
+    pub texture_bytes_uploaded: usize,
+    /// Number of full texture uploads since the previous draw that were skipped,
+    /// because the texture already had the same pixels and options.
+    /// The painter keeps a copy of the last full upload of each texture to compare them.
+    pub texture_uploads_skipped: usize,
+}
+
+/// Contains everything needed to render the gui.
+pub struct Painter {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    /// Graphics pipeline used to render the gui.
+    pub pipeline: Arc<GraphicsPipeline>,
+    /// Texture sampler used to render the gui.
+    pub sampler: Arc<Sampler>,
+    /// Samplers created on demand, one per distinct [`TextureOptions`].
+    /// There are only a handful of possible combinations, so this never grows large.
+    samplers: HashMap<TextureOptions, Arc<Sampler>>,
+    /// Filter of all samplers, see [`Painter::set_texture_filter`]
+    texture_filter: Filter,
+    images: HashMap<egui::TextureId, TextureImage>,
+    texture_sets: HashMap<egui::TextureId, Arc<PersistentDescriptorSet>>,
+    /// Views and samplers of the textures instead of sets, see [`PainterBuilder::use_push_descriptors`]
+    texture_views: HashMap<egui::TextureId, PushedTexture>,
+    texture_options: HashMap<egui::TextureId, TextureOptions>,
+    texture_free_queue: Vec<egui::TextureId>,
+    /// Pipelines created by [`Painter::set_subpass`], keyed by render pass address and subpass index
+    pipelines: HashMap<(usize, u32), Arc<GraphicsPipeline>>,
+    /// Variants of pipelines, keyed by the address of the default variant,
+    /// see [`Painter::pipeline_variant`]
+    pipeline_variants:
+        HashMap<(usize, PipelineVariant), (Arc<GraphicsPipeline>, Arc<GraphicsPipeline>)>,
+    /// Context whose textures are stored in the fields above
+    context: ContextId,
+    /// Textures of the other contexts, swapped in by [`Painter::select_context`]
+    inactive_contexts: HashMap<ContextId, ContextState>,
+    /// Textures retired in a given frame, kept alive until [`Painter::collect_garbage`] drops them.
+    garbage: Vec<(u64, RetiredTexture)>,
+    /// Collected images that partial updates copy into instead of allocating a new image,
+    /// at most one per size and format
+    spare_images: HashMap<([u32; 2], Format), Arc<StorageImage>>,
+    frame: u64,
+    /// One set of reusable geometry buffers per frame in flight, empty to use the pools instead.
+    geometry: Vec<Option<GeometryBuffers>>,
+    next_geometry_slot: usize,
+    /// Pools the geometry is taken from unless frames in flight are configured
+    vertex_pool: CpuBufferPool<Vertex>,
+    index_pool_u16: CpuBufferPool<u16>,
+    index_pool_u32: CpuBufferPool<u32>,
+    /// Staging memory of texture uploads
+    staging_pool: CpuBufferPool<u8>,
+    /// Render pass and pipeline used by [`Painter::render_to_image`] for each image format,
+    /// created on first use.
+    offscreen: HashMap<Format, (Arc<RenderPass>, Arc<GraphicsPipeline>)>,
+    /// Image and framebuffer used by [`Painter::draw_to_intermediate`], until its size changes.
+    intermediate: Option<(Arc<ImageView<Arc<StorageImage>>>, Arc<Framebuffer>)>,
+    stats: PaintStats,
+    /// Contexts that [`Painter::update_textures`] was called for
+    updated_contexts: HashSet<ContextId>,
+    /// Texture bytes uploaded since the last draw, reported in the next [`PaintStats`]
+    texture_bytes_uploaded: usize,
+    /// Unchanged texture uploads skipped since the last draw, reported in the next [`PaintStats`]
+    texture_uploads_skipped: usize,
+    /// The last full upload of each texture of each context, a copy of its pixels
+    last_uploads: HashMap<(ContextId, TextureId), LastUpload>,
+    vertex_color_mode: VertexColorMode,
+    pixel_snapping: bool,
+    /// Whether meshes may be merged into earlier draw calls, see [`Painter::set_batch_reordering`]
+    batch_reordering: bool,
+    /// Whether clip rects are outlined, see [`Painter::set_debug_clip_rects`]
+    debug_clip_rects: bool,
+    /// Whether triangle edges are drawn instead of filled triangles, see [`Painter::set_wireframe`]
+    wireframe: bool,
+    /// Brightness of white in the output, see [`Painter::set_sdr_white_level`]
+    sdr_white_level: f32,
+    /// Pushed to the shaders for animations, see [`Painter::set_frame_time`]
+    time_seconds: f32,
+    frame_index: u32,
+    /// Per-view positions of the gui in multiview subpasses
+    view_offsets: [ViewOffset; 2],
+    /// Scale of the context that was drawn last, used for scissors and pixel snapping
+    pixels_per_point: f32,
+    /// Options set with [`Painter::set_tessellation_options`], used instead of the context's
+    tessellation_options: Option<TessellationOptions>,
+    /// Scale set with [`Painter::set_pixels_per_point`], used instead of the context's
+    pixels_per_point_override: Option<f32>,
+    /// Matrix applied to the gui's clip space positions by [`Painter::draw_with_transform`]
+    transform: Option<[[f32; 4]; 4]>,
+    /// Pre-rotation of the swapchain, see [`Painter::set_surface_transform`]
+    surface_transform: SurfaceTransform,
+    /// Region of the framebuffer to draw into, see [`Painter::set_target_viewport`]
+    target_viewport: Option<Viewport>,
+    /// Pan and zoom of the gui in points, see [`Painter::set_ui_transform`]
+    ui_transform: Option<[[f32; 3]; 3]>,
+    /// Descriptor sets of registered user views, keyed by the addresses of view and sampler
+    set_cache: HashMap<(usize, usize), Weak<PersistentDescriptorSet>>,
+    next_user_texture: u64,
+    /// Id drawing the font atlas of the selected context, see [`Painter::font_texture_id`]
+    font_texture_alias: Option<TextureId>,
+    /// Descriptor set provided by the user for the font texture
+    font_set_override: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+    device_local_geometry: bool,
+    color_space: OutputColorSpace,
+    /// Options of textures other than the font atlas that have none set explicitly
+    user_texture_options: TextureOptions,
+    /// Whether the font atlas has mips unless it has options set, see [`PainterBuilder::font_mipmaps`]
+    font_mipmaps: bool,
+    /// Blending of the painter's pipelines, see [`PainterBuilder::blend`]
+    blend: AttachmentBlend,
+    /// Geometry recorded by [`Painter::upload_meshes`] for the next [`Painter::draw_uploaded`]
+    uploaded: Option<PreparedGeometry>,
+    /// Geometry last drawn for each context, see [`Painter::draw_unchanged`]
+    last_geometry: HashMap<ContextId, PreparedGeometry>,
+    /// Texture array of all contexts, see [`PainterBuilder::bindless_textures`]
+    bindless: Option<BindlessTextures>,
+    /// Whether textures are pushed instead of bound as sets, see [`PainterBuilder::use_push_descriptors`]
+    push_descriptors: bool,
+    /// Command buffers recorded by [`Painter::draw_cached`] for each context
+    cached_draws: HashMap<ContextId, cached::CachedDraw>,
+    /// Shaders used instead of the painter's, see [`PainterBuilder::custom_shaders`]
+    custom_shaders: Option<ShaderModules>,
+    /// Creates the set bound to set 1, see [`PainterBuilder::user_descriptor_set`]
+    user_descriptor_set: Option<UserDescriptorSetFn>,
+    /// Shader files reloaded by [`Painter::poll_shader_reload`]
+    #[cfg(feature = "hot-reload")]
+    shader_watch: Option<hot_reload::ShaderWatch>,
+}
+
+/// A draw call into the merged geometry of a frame.
+#[derive(Clone)]
+struct Batch {
+    clip: Rect,
+    /// Area covered by the merged meshes, only computed with batch reordering
+    bounds: Rect,
+    texture_id: TextureId,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+}
+
+/// Geometry of a frame that was written to buffers and is ready to be drawn.
+#[derive(Clone, Default)]
+struct PreparedGeometry {
+    /// Number of meshes before merging
+    meshes: usize,
+    vertices: usize,
+    indices: usize,
+    batches: Vec<Batch>,
+    /// `None` if there's nothing to draw
+    buffers: Option<(VertexBuffer, IndexBuffer)>,
+}
+
+/// The gui of a frame, tessellated and written to buffers by [`Painter::prepare`],
+/// which can be drawn any number of times with [`Painter::draw_prepared`].
+///
+/// Cloning it is cheap, the buffers are shared.
+#[derive(Clone)]
+pub struct PreparedFrame {
+    geometry: PreparedGeometry,
+}
+
+/// A draw call of the gui, returned by [`Painter::draw_commands`]
+/// to be recorded by a renderer of your own.
+#[derive(Clone)]
+pub struct EguiDrawCommand {
+    /// Vertices of the draw call, starting at the first one its indices refer to.
+    /// Each vertex has the layout of [`egui::epaint::Vertex`].
+    pub vertices: Arc<dyn BufferAccess>,
+    /// Indices of the draw call, relative to the start of `vertices`
+    pub indices: Arc<dyn BufferAccess>,
+    /// Type of the elements of `indices`
+    pub index_type: IndexType,
+    /// Number of indices to draw
+    pub index_count: u32,
+    /// Clip rect of the draw call in framebuffer pixels
+    pub scissor: Scissor,
+    /// Texture to sample, see [`Painter::texture_descriptor_set`]
+    pub texture_id: TextureId,
+    /// Position of the draw call in the whole buffers, which [`Painter::draw`] binds once
+    first_index: u32,
+    vertex_offset: i32,
+}
+
+impl Painter {
+    /// Pass in the vulkano [`Device`], [`Queue`] and [`Subpass`]
+    /// that you want to use to render the gui.
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        subpass: Subpass,
+    ) -> Result<Self, PainterCreationError> {
+        Self::builder(device, queue, subpass).build()
+    }
+
+    /// Creates a painter that shares the pipeline and sampler of another painter,
+    /// i.e. its [`Painter::pipeline`] and [`Painter::sampler`], instead of creating its own,
+    /// e.g. for a second window whose render pass is compatible with the first one's.
+    ///
+    /// The caller is responsible for passing a pipeline and sampler created by a painter with
+    /// the default settings of [`PainterBuilder`]. Only the render pass is checked:
+    /// if the pipeline's subpass isn't compatible with `subpass`,
+    /// this returns [`PainterCreationError::IncompatibleSubpass`].
+    pub fn from_parts(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        pipeline: Arc<GraphicsPipeline>,
+        sampler: Arc<Sampler>,
+        subpass: Subpass,
+    ) -> Result<Self, PainterCreationError> {
+        validate_subpass(&subpass, OutputColorSpace::default())?;
+        validate_pipeline(&pipeline)?;
+        let shared = pipeline.subpass();
+        if shared.index() != subpass.index()
+            || !shared
+                .render_pass()
+                .is_compatible_with(subpass.render_pass())
+        {
+            return Err(PainterCreationError::IncompatibleSubpass {
+                expected: format!(
+                    "a subpass compatible with subpass {} of the shared pipeline",
+                    shared.index()
+                ),
+                found: format!("subpass {} of an incompatible render pass", subpass.index()),
+            });
+        }
+        Self::builder(device, queue, subpass).build_with(pipeline, sampler, None)
+    }
+
+    /// Creates a painter that draws with a pipeline and sampler of your own,
+    /// in the subpass the pipeline was created for.
+    ///
+    /// Create them with [`create_pipeline`] and [`create_sampler`] to change their settings,
+    /// e.g. the cull mode. Pipelines whose vertex input, push constants or descriptor sets
+    /// don't match the painter's fail with [`PainterCreationError::IncompatiblePipeline`].
+    /// Pipeline variants, e.g. for [`Painter::set_wireframe`], are created with the default
+    /// [`PipelineConfig`].
+    pub fn with_pipeline(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        pipeline: Arc<GraphicsPipeline>,
+        sampler: Arc<Sampler>,
+    ) -> Result<Self, PainterCreationError> {
+        let subpass = pipeline.subpass().clone();
+        Self::from_parts(device, queue, pipeline, sampler, subpass)
+    }
+
+    /// Like [`Painter::new`], but returns a [`PainterBuilder`] to configure the painter first.
+    pub fn builder(device: Arc<Device>, queue: Arc<Queue>, subpass: Subpass) -> PainterBuilder {
+        PainterBuilder {
+            device,
+            queue,
+            subpass,
+            color_space: OutputColorSpace::default(),
+            device_local_geometry: false,
+            user_texture_options: TextureOptions::default(),
+            font_mipmaps: false,
+            bindless_textures: false,
+            push_descriptors: false,
+            wireframe: false,
+            blend: premultiplied_alpha_blend(),
+            custom_shaders: None,
+            user_descriptor_set: None,
+            #[cfg(feature = "hot-reload")]
+            shader_paths: None,
+        }
+    }
+
+    /// Returns the largest width or height of a texture the device supports.
+    ///
+    /// Pass it to egui at startup, e.g. to `egui_winit::State::new` or as
+    /// [`egui::RawInput::max_texture_side`], so egui never builds a font atlas larger than
+    /// the GPU allows, which would fail to upload on low-end GPUs.
+    pub fn max_texture_side(&self) -> usize {
+        max_texture_side(&self.device.physical_device())
+    }
+
+    /// Returns the egui settings that this painter needs, derived from the device.
+    ///
+    /// Apply them to the [`egui::RawInput`] of every frame with [`ContextOptions::apply`],
+    /// unless `egui_winit::State` was created with [`max_texture_side`], which already sets them.
+    /// Colors don't need configuring: egui's vertex colors and textures are premultiplied,
+    /// which is what the painter blends.
+    /// `pixels_per_point` should come from the window, e.g. through egui-winit;
+    /// see [`Painter::set_pixels_per_point`] if the framebuffer is scaled differently.
+    pub fn context_options(&self) -> ContextOptions {
+        ContextOptions {
+            max_texture_side: self.max_texture_side(),
+        }
+    }
+
+    /// Recreates [`Painter::pipeline`] for another subpass, e.g. after recreating the render pass
+    /// because the swapchain format changed. Textures, descriptor sets and samplers are kept,
+    /// so this is cheap enough to call whenever the render pass changes.
+    ///
+    /// Pipelines are cached per render pass and subpass index,
+    /// so windows sharing a render pass also share a pipeline, see [`Painter::select_context`].
+    pub fn set_subpass(&mut self, subpass: Subpass) -> Result<(), PainterCreationError> {
+        self.pipeline = self.pipeline_for(subpass)?;
+        Ok(())
+    }
+
+    /// Get the pipeline for a subpass from the cache, or validate the subpass and create it
+    fn pipeline_for(
+        &mut self,
+        subpass: Subpass,
+    ) -> Result<Arc<GraphicsPipeline>, PainterCreationError> {
+        let key = (Arc::as_ptr(subpass.render_pass()) as usize, subpass.index());
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            return Ok(pipeline.clone());
+        }
+
+        validate_subpass(&subpass, self.color_space)?;
+        let pipeline = create_pipeline_variant(
+            self.device.clone(),
+            subpass,
+            self.pipeline_config(),
+            PipelineVariant::default(),
+            self.custom_shaders.as_ref(),
+        )
+        .map_err(custom_shaders::describe_error)?;
+        // Stale entries keep their render pass alive, so its address can't be reused
+        self.pipelines.insert(key, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    /// Settings of the pipelines the painter creates itself
+    fn pipeline_config(&self) -> PipelineConfig {
+        PipelineConfig {
+            color_space: self.color_space,
+            blend: self.blend,
+            ..PipelineConfig::default()
+        }
+    }
+
+    /// Get a variant of a pipeline for the same subpass from the cache, or create it
+    fn pipeline_variant(
+        &mut self,
+        pipeline: &Arc<GraphicsPipeline>,
+        variant: PipelineVariant,
+    ) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+        if variant == PipelineVariant::default() {
+            return Ok(pipeline.clone());
+        }
+        let key = (Arc::as_ptr(pipeline) as usize, variant);
+        if let Some((_, variant_pipeline)) = self.pipeline_variants.get(&key) {
+            return Ok(variant_pipeline.clone());
+        }
+        let variant_pipeline = create_pipeline_variant(
+            self.device.clone(),
+            pipeline.subpass().clone(),
+            self.pipeline_config(),
+            variant,
+            self.custom_shaders.as_ref(),
+        )?;
+        // The original pipeline is kept alive with its variant, so its address can't be reused
+        self.pipeline_variants
+            .insert(key, (pipeline.clone(), variant_pipeline.clone()));
+        Ok(variant_pipeline)
+    }
+
+    /// Sets the [`TextureOptions`] used to sample the texture with the given id,
+    /// e.g. to let a tiled texture repeat when drawn with UVs outside of `0..1`.
+    /// Can be called before or after the texture is uploaded.
+    pub fn set_texture_options(
+        &mut self,
+        texture_id: TextureId,
+        options: TextureOptions,
+    ) -> Result<(), UpdateTexturesError> {
+        self.texture_options.insert(texture_id, options);
+        self.update_texture_set(texture_id)
+    }
+
+    /// Uses the given descriptor set to draw everything that uses egui's font texture,
+    /// instead of the set the painter creates for it, e.g. to allocate it from your own pool.
+    /// While a set is installed, the painter keeps uploading the font texture
+    /// but doesn't create descriptor sets for it anymore.
+    /// Pass `None` to go back to the painter's own descriptor set.
+    ///
+    /// The set has to match the layout of set 0 of [`Painter::pipeline`]:
+    /// binding 0 is a combined image sampler (`sampler2D`) used by the fragment shader.
+    /// You can create it from `painter.pipeline.layout().descriptor_set_layouts()[0]`.
+    pub fn set_font_descriptor_set(
+        &mut self,
+        set: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+    ) -> Result<(), UpdateTexturesError> {
+        let font_id = TextureId::default();
+        self.font_set_override = set;
+        if self.font_set_override.is_some() {
+            let old_set = self.texture_sets.remove(&font_id);
+            self.retire(None, old_set);
+            self.texture_views.remove(&font_id);
+            self.remove_bindless_texture(self.context, font_id);
+            Ok(())
+        } else {
+            self.update_texture_set(font_id)
+        }
+    }
+
+    /// (Re)create the descriptor set of a texture from its current image and options
+    fn update_texture_set(&mut self, texture_id: TextureId) -> Result<(), UpdateTexturesError> {
+        if texture_id == TextureId::default() && self.font_set_override.is_some() {
+            return Ok(());
+        }
+        let image = match self.images.get(&texture_id) {
+            Some(image) => image.clone(),
+            None => return Ok(()),
+        };
+
+        let options = self.options_for(texture_id);
+        if self.push_descriptors {
+            let texture = (image.view()?, self.sampler_for(options)?);
+            self.texture_views.insert(texture_id, texture);
+            return Ok(());
+        }
+        if self.bindless.is_some() {
+            let sampler = self.sampler_for(options)?;
+            self.insert_bindless_texture(texture_id, image.view()?, sampler);
+        }
+        let set = self.create_texture_set(image, options)?;
+        if let Some(old_set) = self.texture_sets.insert(texture_id, set) {
+            self.retire(None, Some(old_set));
+        }
+        Ok(())
+    }
+
+    /// Get the options to sample the given texture with
+    fn options_for(&self, texture_id: TextureId) -> TextureOptions {
+        match self.texture_options.get(&texture_id) {
+            Some(options) => *options,
+            None if texture_id == TextureId::default() => TextureOptions {
+                mipmaps: self.font_mipmaps,
+                ..TextureOptions::default()
+            },
+            None => self.user_texture_options,
+        }
+    }
+
+    /// Get the descriptor set to draw the given texture with
+    fn texture_set(&self, texture_id: TextureId) -> Option<Arc<dyn DescriptorSet + Send + Sync>> {
+        let texture_id = match self.font_texture_alias {
+            Some(alias) if alias == texture_id => TextureId::default(),
+            _ => texture_id,
+        };
+        if texture_id == TextureId::default() {
+            if let Some(set) = &self.font_set_override {
+                return Some(set.clone());
+            }
+        }
+        self.texture_sets
+            .get(&texture_id)
+            .map(|set| set.clone() as Arc<dyn DescriptorSet + Send + Sync>)
+    }
+
+    /// Keep a replaced or freed texture alive until the GPU is done with it
+    fn retire(&mut self, image: Option<TextureImage>, set: Option<Arc<PersistentDescriptorSet>>) {
+        if image.is_some() || set.is_some() {
+            let texture = RetiredTexture { image, set };
+            self.garbage.push((self.frame, texture));
+        }
+    }
+
+    /// Retire the image and descriptor set of a texture
+    fn retire_texture(&mut self, texture_id: &TextureId) {
+        self.last_uploads.remove(&(self.context, *texture_id));
+        let image = self.images.remove(texture_id);
+        let set = self.texture_sets.remove(texture_id);
+        self.texture_views.remove(texture_id);
+        self.retire(image, set);
+        self.remove_bindless_texture(self.context, *texture_id);
+    }
+
+    /// Drops textures that were replaced or freed more than `frames_in_flight` frames ago.
+    ///
+    /// Textures that might still be used by the GPU are never modified or destroyed in place,
+    /// so you don't need to wait for the previous frame when textures change.
+    /// Call this once per frame after submitting the command buffer.
+    ///
+    /// One dropped image of each size is kept as a spare, which the next partial update of a
+    /// texture with that size copies into, so e.g. the font atlas alternates between two images
+    /// instead of allocating a new one for every update.
+    /// This costs the memory of one extra image per texture size that was partially updated.
+    pub fn collect_garbage(&mut self, frames_in_flight: usize) {
+        self.frame += 1;
+        let collected = collect_retired(&mut self.garbage, self.frame, frames_in_flight);
+        for texture in collected {
+            if let Some(TextureImage::Storage(image)) = texture.image {
+                // Views handed out by `font_texture_view` might still be sampled
+                drop(texture.set);
+                if Arc::strong_count(&image) == 1 {
+                    let key = (image.dimensions().width_height(), image.format());
+                    self.spare_images.entry(key).or_insert(image);
+                }
+            }
+        }
+    }
+
+    /// Take the spare image with the given size and format, or create a new one
+    fn storage_image(
+        &mut self,
+        dimensions: [u32; 2],
+        format: Format,
+    ) -> Result<Arc<StorageImage>, ImageCreationError> {
+        match self.spare_images.remove(&(dimensions, format)) {
+            Some(image) => Ok(image),
+            None => create_storage_image(self.queue.clone(), dimensions, format),
+        }
+    }
+
+    /// Keep `frames_in_flight` independent sets of vertex and index buffers and cycle through
+    /// them on every [`Painter::draw`], instead of allocating new buffers each time.
+    ///
+    /// This should be at least the number of frames your application has in flight,
+    /// so geometry written for one frame never aliases geometry the GPU is still reading.
+    /// A set that is still in use anyway is replaced by a newly allocated one.
+    /// `0` (the default) takes the geometry from buffer pools instead,
+    /// which reuse their memory once the GPU is done with it.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        self.geometry = (0..frames_in_flight).map(|_| None).collect();
+        self.next_geometry_slot = 0;
+    }
+
+    /// Switches the minification and magnification filter of all textures,
+    /// e.g. to [`Filter::Nearest`] for a pixel perfect mode. Defaults to [`Filter::Linear`].
+    ///
+    /// Recreates the samplers and the descriptor sets of all textures of all contexts,
+    /// including registered user images and their options, without re-uploading anything.
+    /// Sets still used by frames in flight are kept alive until [`Painter::collect_garbage`].
+    pub fn set_texture_filter(&mut self, filter: Filter) -> Result<(), UpdateTexturesError> {
+        if filter == self.texture_filter {
+            return Ok(());
+        }
+        self.texture_filter = filter;
+        self.samplers.clear();
+        self.sampler = self.sampler_for(TextureOptions::default())?;
+
+        let selected = self.context;
+        let contexts: Vec<ContextId> = self.inactive_contexts.keys().copied().collect();
+        for context in contexts.into_iter().chain(std::iter::once(selected)) {
+            self.select_context(context);
+            if let Err(err) = self.update_texture_sets() {
+                self.select_context(selected);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Recreate the descriptor sets of all textures of the selected context
+    fn update_texture_sets(&mut self) -> Result<(), UpdateTexturesError> {
+        let texture_ids: Vec<TextureId> = self.images.keys().copied().collect();
+        for texture_id in texture_ids {
+            self.update_texture_set(texture_id)?;
+        }
+        Ok(())
+    }
+
+    /// Get the sampler for the given options, creating it if necessary
+    fn sampler_for(
+        &mut self,
+        options: TextureOptions,
+    ) -> Result<Arc<Sampler>, SamplerCreationError> {
+        if let Some(sampler) = self.samplers.get(&options) {
+            return Ok(sampler.clone());
+        }
+        let config = SamplerConfig {
+            texture_options: options,
+            filter: self.texture_filter,
+        };
+        let sampler = create_sampler(self.device.clone(), config)?;
+        self.samplers.insert(options, sampler.clone());
+        Ok(sampler)
+    }
+
+    /// Create the descriptor set used to sample the given image
+    fn create_texture_set(
+        &mut self,
+        image: TextureImage,
+        options: TextureOptions,
+    ) -> Result<Arc<PersistentDescriptorSet>, UpdateTexturesError> {
+        let sampler = self.sampler_for(options)?;
+        let view = image.view()?;
+
+        // User views can be registered under several ids, which then share one descriptor set
+        let cache_key = match image {
+            TextureImage::User(_) => Some((
+                Arc::as_ptr(&view) as *const () as usize,
+                Arc::as_ptr(&sampler) as usize,
+            )),
+            _ => None,
+        };
+        if let Some(key) = cache_key {
+            if let Some(set) = self.set_cache.get(&key).and_then(Weak::upgrade) {
+                return Ok(set);
+            }
+        }
+
+        let layout = &self.pipeline.layout().descriptor_set_layouts()[0];
+        let set = PersistentDescriptorSet::new(
+            layout.clone(),
+            [WriteDescriptorSet::image_view_sampler(0, view, sampler)],
+        )?;
+
+        if let Some(key) = cache_key {
+            // A set keeps its view alive, so once no id uses a set its view may be gone
+            self.set_cache.retain(|_, set| set.strong_count() > 0);
+            self.set_cache.insert(key, Arc::downgrade(&set));
+        }
+        Ok(set)
+    }
+
+    /// Registers an image view of your own as a user texture and returns its id,
+    /// which can be drawn like any other texture, e.g. with [`egui::Ui::image`].
+    ///
+    /// The view has to be a 2D view of a single array layer that is usable as a sampled image,
+    /// array and cube views return [`UpdateTexturesError::UnsupportedImageView`].
+    /// To show a layer or face of such an image, register a 2D view of just that layer.
+    ///
+    /// Registering the same view again under another id (e.g. for slices of an atlas with
+    /// different UVs) reuses its descriptor set instead of allocating a new one.
+    pub fn register_user_image(
+        &mut self,
+        view: Arc<dyn ImageViewAbstract>,
+        options: TextureOptions,
+    ) -> Result<TextureId, UpdateTexturesError> {
+        let array_layers = view.array_layers();
+        let array_layers = array_layers.end - array_layers.start;
+        if view.ty() != ImageViewType::Dim2d || array_layers != 1 {
+            return Err(UpdateTexturesError::UnsupportedImageView {
+                view_type: view.ty(),
+                array_layers,
+            });
+        }
+
+        let texture_id = TextureId::User(self.next_user_texture);
+        self.next_user_texture += 1;
+        self.images.insert(texture_id, TextureImage::User(view));
+        self.set_texture_options(texture_id, options)?;
+        Ok(texture_id)
+    }
+
+    /// Stops drawing a texture registered with [`Painter::register_user_image`]
+    /// and releases the painter's references to it once the GPU is done with it.
+    pub fn unregister_user_image(&mut self, texture_id: TextureId) {
+        self.retire_texture(&texture_id);
+        self.texture_options.remove(&texture_id);
+    }
+
+    /// Returns a view of egui's font atlas as of the latest [`Painter::update_textures`],
+    /// e.g. to check what was uploaded when text renders incorrectly.
+    /// `None` until the font atlas of the selected context has been uploaded.
+    pub fn font_texture_view(&self) -> Option<Arc<dyn ImageViewAbstract>> {
+        self.images
+            .get(&TextureId::default())
+            .and_then(|image| image.view().ok())
+    }
+
+    /// Returns a texture id that draws egui's font atlas, e.g. with [`egui::Ui::image`]
+    /// in a debug window.
+    ///
+    /// Unlike registering [`Painter::font_texture_view`] with [`Painter::register_user_image`],
+    /// the id always shows the atlas of the selected context as of its latest update,
+    /// even after egui reallocated it.
+    pub fn font_texture_id(&mut self) -> TextureId {
+        if let Some(texture_id) = self.font_texture_alias {
+            return texture_id;
+        }
+        let texture_id = TextureId::User(self.next_user_texture);
+        self.next_user_texture += 1;
+        self.font_texture_alias = Some(texture_id);
+        texture_id
+    }
+
+    /// Stage the pixels of a delta to be copied into the image
+    fn write_image_delta(
+        image: Arc<StorageImage>,
+        delta: &ImageDelta,
+        uploads: &mut Vec<PendingUpload>,
+    ) {
+        let offset = match delta.pos {
+            None => [0, 0, 0],
+            Some(pos) => [pos[0] as u32, pos[1] as u32, 0],
+        };
+        uploads.push(PendingUpload {
+            image,
+            pixels: image_data_bytes(&delta.image),
+            offset,
+            size: [delta.image.width() as u32, delta.image.height() as u32, 1],
+            mip_level: 0,
+        });
+    }
+
+    /// Write the pixels of all uploads into one staging buffer
+    /// and record copying them into their images
+    fn record_uploads<P>(
+        &mut self,
+        uploads: Vec<PendingUpload>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+    ) -> Result<(), UpdateTexturesError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        if uploads.is_empty() {
+            return Ok(());
+        }
+
+        let mut bytes = Vec::with_capacity(uploads.iter().map(|u| u.pixels.len()).sum());
+        let mut ranges = Vec::with_capacity(uploads.len());
+        for upload in &uploads {
+            // The offset of each copy has to be a multiple of the texel size
+            let padding = (STAGING_ALIGNMENT - bytes.len() % STAGING_ALIGNMENT) % STAGING_ALIGNMENT;
+            bytes.resize(bytes.len() + padding, 0);
+            let start = bytes.len() as DeviceSize;
+            ranges.push(start..start + upload.pixels.len() as DeviceSize);
+            bytes.extend_from_slice(&upload.pixels);
+        }
+        let staging = BufferSlice::from_typed_buffer_access(self.staging_pool.chunk(bytes)?);
+
+        for (upload, range) in uploads.into_iter().zip(ranges) {
+            builder.copy_buffer_to_image_dimensions(
+                staging.slice(range).unwrap(),
+                upload.image,
+                upload.offset,
+                upload.size,
+                0,
+                1,
+                upload.mip_level,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Create an image with a full mip chain from RGBA pixels,
+    /// staging the upload of every mip level
+    fn create_mipmapped_image(
+        &mut self,
+        dimensions: [u32; 2],
+        format: Format,
+        pixels: &[u8],
+        uploads: &mut Vec<PendingUpload>,
+    ) -> Result<Arc<ImmutableImage>, UpdateTexturesError> {
+        let usage = ImageUsage {
+            transfer_destination: true,
+            sampled: true,
+            ..ImageUsage::none()
+        };
+        let (image, initializer) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            ImageDimensions::Dim2d {
+                width: dimensions[0],
+                height: dimensions[1],
+                array_layers: 1,
+            },
+            format,
+            MipmapsCount::Log2,
+            usage,
+            ImageCreateFlags::none(),
+            ImageLayout::ShaderReadOnlyOptimal,
+            self.device.active_queue_families(),
+        )?;
+
+        let srgb = format == Format::R8G8B8A8_SRGB;
+        let mut level_pixels = pixels.to_vec();
+        let mut level_dimensions = dimensions;
+        for level in 0..image.mip_levels() {
+            if level > 0 {
+                level_pixels = downsample_pixels(&level_pixels, level_dimensions, srgb);
+                level_dimensions = level_dimensions.map(|side| (side / 2).max(1));
+            }
+            uploads.push(PendingUpload {
+                image: initializer.clone(),
+                pixels: level_pixels.clone(),
+                offset: [0, 0, 0],
+                size: [level_dimensions[0], level_dimensions[1], 1],
+                mip_level: level,
+            });
+        }
+        Ok(image)
+    }
+
+    /// Returns true if [`Painter::update_textures`] would record uploads for this delta,
+    /// e.g. to decide which command buffer or queue to record them with before recording.
+    ///
+    /// Deltas that only free textures return false,
+    /// but still have to be passed to [`Painter::update_textures`].
+    pub fn needs_texture_update(&self, textures_delta: &TexturesDelta) -> bool {
+        textures_delta.set.iter().any(|(texture_id, delta)| {
+            // Partial updates of dropped or user images are skipped
+            delta.is_whole()
+                || matches!(
+                    self.images.get(texture_id),
+                    Some(TextureImage::Storage(_) | TextureImage::Mipmapped(..))
+                )
+        })
+    }
+
+    /// Uploads all newly created and modified textures to the GPU.
+    /// Has to be called before entering the first render pass.  
+    /// The uploads are only recorded into the builder, from one staging buffer per call holding
+    /// the pixels of all textures, so they're submitted with the rest of the frame,
+    /// on any queue of the device.
+    /// Replaced textures are kept alive until [`Painter::collect_garbage`] drops them,
+    /// so there is no need to wait for the last frame to finish.
+    /// Partial updates, e.g. of the font atlas, write into a copy of the image,
+    /// alternating between two images once the old one is collected.
+    /// Every partial update copies the whole image on the GPU, however small the delta is,
+    /// so a 2048x2048 atlas costs a 16 MiB copy per update and the memory of one spare image.
+    ///
+    /// Textures in [`TexturesDelta::free`] can still be used by the shapes of the same frame,
+    /// so their images, descriptor sets and options are released after the next draw,
+    /// or by the next call of this method for frames drawn with [`Painter::draw_prepared`]
+    /// or returned by [`Painter::draw_commands`].
+    pub fn update_textures<P>(
+        &mut self,
+        textures_delta: TexturesDelta,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+    ) -> Result<UpdateTexturesResult, UpdateTexturesError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        #[cfg(feature = "puffin")]
+        puffin::profile_scope!("update_textures");
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "update_textures",
+            set = textures_delta.set.len(),
+            free = textures_delta.free.len()
+        )
+        .entered();
+
+        let mut result = UpdateTexturesResult::default();
+        self.updated_contexts.insert(self.context);
+
+        // Textures freed by a frame drawn with `draw_prepared`, which can't tell its last draw
+        self.free_textures();
+
+        for texture_id in textures_delta.free {
+            self.texture_free_queue.push(texture_id);
+            result.freed.push(texture_id);
+        }
+
+        // The pixels of all deltas are staged and copied together, including the deltas
+        // before a failing one, whose images were already replaced
+        let mut uploads = Vec::new();
+        let mut staged = Ok(());
+        // The set is a hash map, so sort it to update and report textures in a stable order
+        let mut deltas: Vec<_> = textures_delta.set.iter().collect();
+        deltas.sort_by_key(|&(texture_id, _)| *texture_id);
+        for (texture_id, delta) in deltas {
+            staged = self.stage_delta(*texture_id, delta, builder, &mut uploads, &mut result);
+            if staged.is_err() {
+                break;
+            }
+        }
+        self.record_uploads(uploads, builder)?;
+        staged?;
+
+        Ok(result)
+    }
+
+    /// Remember a full upload of a texture of the selected context
+    fn remember_upload(&mut self, texture_id: TextureId, upload: Option<LastUpload>) {
+        if let Some(upload) = upload {
+            self.last_uploads.insert((self.context, texture_id), upload);
+        }
+    }
+
+    /// Replace or copy the image of a texture for a delta and stage its pixels
+    fn stage_delta<P>(
+        &mut self,
+        texture_id: TextureId,
+        delta: &ImageDelta,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        uploads: &mut Vec<PendingUpload>,
+        result: &mut UpdateTexturesResult,
+    ) -> Result<(), UpdateTexturesError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            texture_id = ?texture_id,
+            whole = delta.is_whole(),
+            bytes_uploaded = delta.image.width() * delta.image.height() * 4,
+            "uploading texture"
+        );
+
+        let max_texture_side = self.max_texture_side();
+        if delta.image.width() > max_texture_side || delta.image.height() > max_texture_side {
+            return Err(UpdateTexturesError::TextureTooLarge {
+                texture_id,
+                size: delta.image.size(),
+                max_texture_side,
+            });
+        }
+
+        // Images can't be empty, e.g. the font atlas of a context without fonts,
+        // so the texture is left without an image and meshes using it aren't drawn
+        if delta.image.width() == 0 || delta.image.height() == 0 {
+            if delta.is_whole() {
+                self.retire_texture(&texture_id);
+            }
+            return Ok(());
+        }
+
+        // egui sometimes sends the same pixels again, e.g. after style changes,
+        // which don't have to be uploaded while the texture still has them.
+        // Matching hashes aren't enough, the pixels are compared to rule out collisions
+        let options = self.options_for(texture_id);
+        let last_upload = if delta.is_whole() {
+            let hash = hash_upload(&delta.image, options);
+            let key = (self.context, texture_id);
+            let unchanged = self.images.contains_key(&texture_id)
+                && self
+                    .last_uploads
+                    .get(&key)
+                    .map_or(false, |last| last.matches(hash, &delta.image, options));
+            if unchanged {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(texture_id = ?texture_id, "skipped unchanged texture");
+                self.texture_uploads_skipped += 1;
+                return Ok(());
+            }
+            Some(LastUpload {
+                hash,
+                options,
+                image: delta.image.clone(),
+            })
+        } else {
+            None
+        };
+        self.texture_bytes_uploaded += delta.image.width() * delta.image.height() * 4;
+
+        // Mipmapped images are uploaded with mips generated on the CPU,
+        // partial updates are applied to their pixels, which are then uploaded again
+        let mipmapped = match self.images.get(&texture_id) {
+            _ if delta.is_whole() && options.mipmaps => Some((
+                [delta.image.width() as u32, delta.image.height() as u32],
+                image_format(&delta.image),
+                image_data_bytes(&delta.image),
+            )),
+            Some(TextureImage::Mipmapped(old_image, pixels)) if !delta.is_whole() => {
+                let dimensions = old_image.dimensions().width_height();
+                let mut pixels = pixels.as_ref().clone();
+                patch_pixels(&mut pixels, dimensions[0] as usize, delta);
+                Some((dimensions, old_image.format(), pixels))
+            }
+            _ => None,
+        };
+        if let Some((dimensions, format, pixels)) = mipmapped {
+            let image = self.create_mipmapped_image(dimensions, format, &pixels, uploads)?;
+            self.retire_texture(&texture_id);
+            self.images
+                .insert(texture_id, TextureImage::Mipmapped(image, Arc::new(pixels)));
+            self.remember_upload(texture_id, last_upload);
+            self.update_texture_set(texture_id)?;
+            result.push(
+                texture_id,
+                if delta.is_whole() {
+                    TextureChange::Reallocated
+                } else {
+                    TextureChange::Updated
+                },
+            );
+            return Ok(());
+        }
+
+        let image = if delta.is_whole() {
+            create_image(self.queue.clone(), &delta.image)?
+        } else {
+            let old_image = match self.images.get(&texture_id) {
+                Some(TextureImage::Storage(image)) => image.clone(),
+                // Registered user images are never updated by egui,
+                // mipmapped images were updated above
+                Some(_) => return Ok(()),
+                // The image was dropped by reset_resources, wait for the next full upload
+                None => return Ok(()),
+            };
+            // The old image might still be in use, so copy it and modify the copy instead,
+            // which reuses the image of an earlier update once the GPU is done with it
+            let dimensions = old_image.dimensions().width_height();
+            let image = self.storage_image(dimensions, old_image.format())?;
+            builder.copy_image(
+                old_image,
+                [0, 0, 0],
+                0,
+                0,
+                image.clone(),
+                [0, 0, 0],
+                0,
+                0,
+                [dimensions[0], dimensions[1], 1],
+                1,
+            )?;
+            image
+        };
+
+        self.retire_texture(&texture_id);
+        self.images
+            .insert(texture_id, TextureImage::Storage(image.clone()));
+        self.remember_upload(texture_id, last_upload);
+        self.update_texture_set(texture_id)?;
+        result.push(
+            texture_id,
+            if delta.is_whole() {
+                TextureChange::Reallocated
+            } else {
+                TextureChange::Updated
+            },
+        );
+
+        Self::write_image_delta(image, delta, uploads);
+        Ok(())
+    }
+
+    /// Drops all textures and their descriptor sets, e.g. to free memory or to recover from a
+    /// device reset, while keeping the pipeline and samplers.
+    /// This includes the textures of contexts that are not currently selected.
+    ///
+    /// Textures are recreated when egui sends the next full [`ImageDelta`] for them.
+    /// For the font atlas this happens after calling [`Context::set_fonts`],
+    /// user textures have to be loaded again with [`Context::load_texture`].
+    pub fn reset_resources(&mut self) {
+        let texture_ids: Vec<TextureId> = self.images.keys().copied().collect();
+        for texture_id in &texture_ids {
+            self.retire_texture(texture_id);
+        }
+        self.texture_sets.clear();
+        self.texture_views.clear();
+        self.texture_free_queue.clear();
+
+        let mut contexts = std::mem::take(&mut self.inactive_contexts);
+        for (&context, textures) in contexts.iter_mut() {
+            self.retire_context_textures(textures);
+            self.remove_bindless_context(context);
+            self.last_uploads.retain(|&(c, _), _| c != context);
+            textures.texture_free_queue.clear();
+        }
+        self.inactive_contexts = contexts;
+    }
+
+    /// Forgets all textures and descriptor sets right away, so every texture is recreated
+    /// from the next full [`ImageDelta`] egui sends for it, see [`Painter::reset_resources`].
+    ///
+    /// Unlike [`Painter::reset_resources`] this also drops retired textures that are waiting for
+    /// [`Painter::collect_garbage`], the spare images kept for partial updates
+    /// and the descriptor set installed with [`Painter::set_font_descriptor_set`], so no handles to the old resources are kept.
+    /// Only call this while the GPU isn't using any of them,
+    /// e.g. after waiting for the device to become idle or after the device was lost.
+    pub fn invalidate_textures(&mut self) {
+        self.reset_resources();
+        self.garbage.clear();
+        self.spare_images.clear();
+        self.font_set_override = None;
+    }
+
+    /// Selects the egui context whose textures and pipeline are used by the following calls,
+    /// so one painter can draw several contexts, e.g. one per window.
+    ///
+    /// Every context numbers its textures independently, so each one gets its own font texture
+    /// and user textures, while samplers and geometry buffers are shared.
+    /// A context starts out with the pipeline of the previously selected one.
+    /// If its window has a different render pass, call [`Painter::set_subpass`] once after
+    /// selecting it for the first time; the pipeline is remembered for later selections.
+    /// Select the context before calling [`Painter::update_textures`] with its
+    /// [`TexturesDelta`] and before drawing its shapes.
+    pub fn select_context(&mut self, context: ContextId) {
+        if context == self.context {
+            return;
+        }
+        let textures = self.inactive_contexts.remove(&context).unwrap_or_default();
+        let previous = ContextState {
+            pipeline: Some(self.pipeline.clone()),
+            images: std::mem::replace(&mut self.images, textures.images),
+            texture_sets: std::mem::replace(&mut self.texture_sets, textures.texture_sets),
+            texture_views: std::mem::replace(&mut self.texture_views, textures.texture_views),
+            texture_options: std::mem::replace(&mut self.texture_options, textures.texture_options),
+            texture_free_queue: std::mem::replace(
+                &mut self.texture_free_queue,
+                textures.texture_free_queue,
+            ),
+        };
+        if let Some(pipeline) = textures.pipeline {
+            self.pipeline = pipeline;
+        }
+        self.inactive_contexts.insert(self.context, previous);
+        self.context = context;
+    }
+
+    /// Returns the context selected by [`Painter::select_context`].
+    pub fn selected_context(&self) -> ContextId {
+        self.context
+    }
+
+    /// Drops all textures of a context that is no longer drawn, e.g. after closing its window.
+    /// Removing the selected context drops its textures, but keeps it selected.
+    pub fn remove_context(&mut self, context: ContextId) {
+        self.updated_contexts.remove(&context);
+        self.last_geometry.remove(&context);
+        self.cached_draws.remove(&context);
+        if context == self.context {
+            self.reset_current_context();
+        } else if let Some(mut textures) = self.inactive_contexts.remove(&context) {
+            self.retire_context_textures(&mut textures);
+            self.remove_bindless_context(context);
+            self.last_uploads.retain(|&(c, _), _| c != context);
+        }
+    }
+
+    /// Retire all textures of the selected context and forget their options
+    fn reset_current_context(&mut self) {
+        let texture_ids: Vec<TextureId> = self.images.keys().copied().collect();
+        for texture_id in &texture_ids {
+            self.retire_texture(texture_id);
+        }
+        self.texture_sets.clear();
+        self.texture_views.clear();
+        self.texture_options.clear();
+        self.texture_free_queue.clear();
+    }
+
+    /// Retire the images and descriptor sets of a context that is not selected
+    fn retire_context_textures(&mut self, textures: &mut ContextState) {
+        for (_, image) in textures.images.drain() {
+            self.retire(Some(image), None);
+        }
+        for (_, set) in textures.texture_sets.drain() {
+            self.retire(None, Some(set));
+        }
+        textures.texture_views.clear();
+    }
+
+    /// Free textures freed by egui, *after* drawing
+    fn free_textures(&mut self) {
+        let texture_free_queue = std::mem::take(&mut self.texture_free_queue);
+        for texture_id in &texture_free_queue {
+            self.retire_texture(texture_id);
+            self.texture_options.remove(texture_id);
+        }
+    }
+
+    /// Advances to the next rendering subpass and uses the [`ClippedShape`]s from [`egui::FullOutput`] to draw the gui.
+    ///
+    /// This is a shortcut for [`Painter::tessellate`] followed by [`Painter::draw_meshes`].
+    ///
+    /// If `window_size_points` is zero or not finite, e.g. while the window is minimized,
+    /// the shapes are discarded without recording any draw calls.
+    ///
+    /// The gui is drawn with the viewport that is currently set on the builder,
+    /// which should cover the whole window. Only the scissor is changed between meshes.
+    ///
+    /// Use this if the gui has its own subpass right after the one you are currently in.
+    /// If the painter was created for the subpass you are already in,
+    /// e.g. to draw the gui as an overlay in the same subpass as your scene,
+    /// use [`Painter::draw_in_current_subpass`] instead.
+    pub fn draw<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        self.draw_meshes(builder, window_size_points, &clipped_meshes)
+    }
+
+    /// Like [`Painter::draw`], but draws the gui into `viewport`, a region of the framebuffer
+    /// in pixels, e.g. the bottom third of a split screen.
+    /// The window size in points is the size of the viewport divided by `pixels_per_point`,
+    /// and egui's input has to be relative to the viewport's origin.
+    ///
+    /// This is [`Painter::set_target_viewport`] for a single draw,
+    /// the target viewport set before is used again afterwards.
+    pub fn draw_in_viewport<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        viewport: Viewport,
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        let window_size_points = [
+            viewport.dimensions[0] / self.pixels_per_point,
+            viewport.dimensions[1] / self.pixels_per_point,
+        ];
+        let target_viewport = self.target_viewport.replace(viewport);
+        let result = self.draw_meshes(builder, window_size_points, &clipped_meshes);
+        self.target_viewport = target_viewport;
+        result
+    }
+
+    /// Like [`Painter::draw`], but draws the gui in the current subpass
+    /// instead of advancing to the next one first.
+    /// The current subpass has to be the one the painter was created for.
+    pub fn draw_in_current_subpass<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        self.draw_meshes_in_current_subpass(builder, window_size_points, &clipped_meshes)
+    }
+
+    /// Tessellates the shapes and writes their geometry to host visible buffers like
+    /// [`Painter::draw`], but returns the draw calls instead of recording them,
+    /// e.g. to translate them into the commands of a renderer that doesn't expose
+    /// [`AutoCommandBufferBuilder`].
+    ///
+    /// Draw the commands in order with [`Painter::pipeline`] and the painter's push constants.
+    /// Commands whose texture has no descriptor set are skipped by [`Painter::draw`].
+    /// Textures freed by this frame's [`TexturesDelta`] keep their descriptor sets until
+    /// the commands are recorded and are released by the next [`Painter::update_textures`].
+    /// [`PainterBuilder::device_local_geometry`] is ignored, since copying the geometry
+    /// needs a builder. Returns no commands if `window_size_points` is zero or not finite.
+    pub fn draw_commands(
+        &mut self,
+        window_size_points: [f32; 2],
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Result<Vec<EguiDrawCommand>, DrawError> {
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        let (mut geometry, triangles) = self.merge_meshes(&clipped_meshes)?;
+        if !geometry.batches.is_empty() {
+            let (vertex_buffer, index_buffer, _) = self.write_host_buffers(&triangles)?;
+            geometry.buffers = Some((vertex_buffer, index_buffer));
+        }
+        Ok(self.commands_for(&geometry, window_size_points))
+    }
+
+    /// Returns the descriptor set a texture of the selected context is drawn with,
+    /// e.g. to record an [`EguiDrawCommand`], or `None` if there's no such texture
+    /// or it's pushed, see [`PainterBuilder::use_push_descriptors`].
+    pub fn texture_descriptor_set(
+        &self,
+        texture_id: TextureId,
+    ) -> Option<Arc<dyn DescriptorSet + Send + Sync>> {
+        self.texture_set(texture_id)
+    }
+
+    /// Like [`Painter::draw_in_current_subpass`], but multiplies the clip space positions of the gui
+    /// with `transform`, e.g. to draw it onto a quad in a 3D scene.
+    /// The matrix is column major, like in GLSL, and maps the window onto `-1.0..=1.0` in x and y.
+    ///
+    /// Clip rects are in the gui's window space, which the transform moves elsewhere,
+    /// so they aren't applied. Shapes that overflow their clip rect, e.g. in scroll areas,
+    /// are drawn in full, shapes that are clipped away entirely are still skipped.
+    pub fn draw_with_transform<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+        transform: [[f32; 4]; 4],
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        self.transform = Some(transform);
+        let result =
+            self.draw_in_current_subpass(builder, window_size_points, egui_ctx, clipped_shapes);
+        self.transform = None;
+        result
+    }
+
+    /// Creates a render pass for [`Painter::draw_full_frame`], with a single subpass that clears
+    /// and stores one color attachment of the given format, e.g. the format of the swapchain.
+    /// Create the framebuffers of the swapchain images from it.
+    pub fn full_frame_render_pass(
+        &self,
+        format: Format,
+    ) -> Result<Arc<RenderPass>, RenderPassCreationError> {
+        vulkano::single_pass_renderpass!(
+            self.device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+            pass: { color: [color], depth_stencil: {} }
+        )
+    }
+
+    /// Draws a frame that shows nothing but the gui, for tools that don't render anything else:
+    /// begins a render pass on `framebuffer` that clears it to `clear_color`,
+    /// draws the gui into its first subpass and ends the render pass.
+    ///
+    /// The framebuffer should be created from [`Painter::full_frame_render_pass`],
+    /// or a render pass of your own with a single cleared color attachment.
+    /// The gui covers the whole framebuffer and is drawn with the painter's [`OutputColorSpace`].
+    pub fn draw_full_frame<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        framebuffer: Arc<Framebuffer>,
+        clear_color: [f32; 4],
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Result<(), DrawFullFrameError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let subpass = Subpass::from(framebuffer.render_pass().clone(), 0).unwrap();
+        let pipeline = self.pipeline_for(subpass)?;
+        let dimensions = framebuffer.dimensions();
+
+        // Geometry has to be written before the render pass in case it's copied to device memory
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        let geometry = self.prepare_geometry(builder, &clipped_meshes)?;
+
+        builder.begin_render_pass(framebuffer, Inline, vec![clear_color.into()])?;
+        builder.set_viewport(
+            0,
+            [Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                depth_range: 0.0..1.0,
+            }],
+        );
+        let window_size_points = [
+            dimensions[0] as f32 / self.pixels_per_point,
+            dimensions[1] as f32 / self.pixels_per_point,
+        ];
+        self.record_prepared(builder, pipeline, window_size_points, geometry)?;
+        builder.end_render_pass()?;
+        Ok(())
+    }
+
+    /// Sets how vertex colors are interpreted by the following draws.
+    /// Defaults to [`VertexColorMode::Srgb`], which is what egui produces.
+    pub fn set_vertex_color_mode(&mut self, mode: VertexColorMode) {
+        self.vertex_color_mode = mode;
+    }
+
+    /// Rounds vertex positions to whole physical pixels when enabled,
+    /// which makes text, separators and 1 point strokes crisper at fractional
+    /// `pixels_per_point` values, e.g. 125% scaling. Defaults to `false`.
+    ///
+    /// This applies to all meshes and can shift the layout by up to half a pixel,
+    /// so smoothly moving shapes may jitter while animating.
+    /// The pixel grid uses the `pixels_per_point` of the context passed to the last
+    /// [`Painter::draw`] or [`Painter::draw_in_current_subpass`],
+    /// unless it's overridden with [`Painter::set_pixels_per_point`].
+    pub fn set_pixel_snapping(&mut self, enabled: bool) {
+        self.pixel_snapping = enabled;
+    }
+
+    /// Merge meshes into earlier draw calls with the same texture and clip rect,
+    /// not just the directly preceding one. Defaults to `false`.
+    ///
+    /// A mesh is only moved before the draw calls in between if it doesn't overlap any of them,
+    /// so blending gives the same result as drawing in egui's order.
+    /// This saves draw calls and texture switches when windows alternate between text and
+    /// images, e.g. a list with an icon and a label per row, at the cost of computing the
+    /// bounds of every mesh. [`PaintStats::draw_calls`] shows how much it saves.
+    pub fn set_batch_reordering(&mut self, enabled: bool) {
+        self.batch_reordering = enabled;
+    }
+
+    /// Scales the linear output colors of the gui, so white has the brightness `white_level`
+    /// in the units of an HDR attachment, e.g. `R16G16B16A16_SFLOAT` created with
+    /// [`OutputColorSpace::ExtendedSrgbLinear`] for an extended sRGB swapchain, where 1.0 is 80 nits.
+    /// Defaults to 1.0, which leaves the colors of SDR attachments unchanged.
+    pub fn set_sdr_white_level(&mut self, white_level: f32) {
+        self.sdr_white_level = white_level;
+    }
+
+    /// Sets the time and frame index pushed to the shaders for the following draws,
+    /// e.g. seconds since startup for an animated effect in [`CustomShaders`].
+    /// Both default to 0 and aren't used by the painter's own shaders.
+    ///
+    /// The vertex shader passes them to the fragment shader as the flat inputs
+    /// `float` at location 3 and `uint` at location 4.
+    /// Command buffers of [`Painter::draw_cached`] keep the values they were recorded with.
+    pub fn set_frame_time(&mut self, time_seconds: f32, frame_index: u32) {
+        self.time_seconds = time_seconds;
+        self.frame_index = frame_index;
+    }
+
+    /// Positions the gui differently in each view of a multiview subpass,
+    /// e.g. for the eyes of a VR headset. Defaults to no offset and a scale of 1.
+    ///
+    /// The painter creates its pipeline for multiview subpasses with the view mask of the subpass,
+    /// which needs the `multiview` device feature the render pass needs anyway.
+    /// Up to two views are supported, other subpasses ignore this.
+    pub fn set_view_offsets(&mut self, views: [ViewOffset; 2]) {
+        self.view_offsets = views;
+    }
+
+    /// Overrides the `pixels_per_point` of the egui context with the scale of the framebuffer,
+    /// e.g. when the swapchain is scaled differently than egui thinks because of
+    /// fractional scaling or a zoom feature. `None` uses the context's value again.
+    ///
+    /// The scale converts clip rects to scissors and is used for pixel snapping,
+    /// while `window_size_points` stays the size of the framebuffer divided by this scale.
+    /// The meshes are still tessellated by egui with the context's value.
+    pub fn set_pixels_per_point(&mut self, pixels_per_point: Option<f32>) {
+        self.pixels_per_point_override = pixels_per_point;
+    }
+
+    /// Rotates the gui to match the `SurfaceTransform` of a pre-rotated swapchain,
+    /// e.g. the `current_transform` of the surface capabilities on Android,
+    /// so it isn't drawn sideways. Defaults to [`SurfaceTransform::Identity`].
+    ///
+    /// `window_size_points` passed to the draw methods stays the unrotated size of the window,
+    /// while the viewport has to cover the whole rotated framebuffer.
+    /// Only the rotations are supported, mirrored transforms are drawn like `Identity`.
+    /// [`Painter::render_to_image`] never rotates the gui.
+    pub fn set_surface_transform(&mut self, transform: SurfaceTransform) {
+        self.surface_transform = transform;
+    }
+
+    /// Draws the gui into a region of the framebuffer instead of all of it,
+    /// e.g. next to a native panel. `None` (the default) uses the viewport that is set on the builder.
+    ///
+    /// The painter sets this viewport before drawing and moves all scissors into it,
+    /// clamped to its extent.
+    /// `window_size_points` passed to the draw methods has to be the size of the viewport
+    /// in points, and egui's input has to be relative to its origin.
+    pub fn set_target_viewport(&mut self, viewport: Option<Viewport>) {
+        self.target_viewport = viewport;
+    }
+
+    /// Pans and zooms the whole gui with a 2D affine transform of its positions in points,
+    /// applied before they are mapped to the window, e.g. to show a scaled gui
+    /// inside a larger framebuffer in a kiosk mode. `None` (the default) draws the gui as is.
+    ///
+    /// The matrix is column major, like a GLSL `mat3`, with the translation in the third column,
+    /// e.g. `[[2.0, 0.0, 0.0], [0.0, 2.0, 0.0], [100.0, 100.0, 1.0]]` doubles the size of the gui
+    /// and moves it 100 points right and down. Clip rects are transformed the same way,
+    /// rotated or sheared ones are replaced by their bounding box.
+    /// egui's input has to be transformed with the inverse matrix by the caller.
+    pub fn set_ui_transform(&mut self, transform: Option<[[f32; 3]; 3]>) {
+        self.ui_transform = transform;
+    }
+
+    /// Uses the context's scale for the next draw, unless it's overridden
+    fn use_pixels_per_point_of(&mut self, egui_ctx: &Context) {
+        self.pixels_per_point = pixels_per_point(self.pixels_per_point_override, egui_ctx);
+    }
+
+    /// Returns statistics about the gui most recently drawn.
+    pub fn last_stats(&self) -> PaintStats {
+        self.stats
+    }
+
+    /// Turns the [`ClippedShape`]s from [`egui::FullOutput`] into triangle meshes,
+    /// which can be inspected or filtered before passing them to [`Painter::draw_meshes`].
+    ///
+    /// Remembers the `pixels_per_point` of the context for drawing the meshes,
+    /// so they can be drawn without the context, e.g. to several targets:
+    /// tessellate once and pass the meshes to each [`Painter::draw_meshes`] or
+    /// [`Painter::draw_meshes_in_current_subpass`] instead of tessellating per target.
+    pub fn tessellate(
+        &mut self,
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Vec<ClippedMesh> {
+        #[cfg(feature = "puffin")]
+        puffin::profile_scope!("tessellate");
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("tessellate", shapes = clipped_shapes.len()).entered();
+
+        self.use_pixels_per_point_of(egui_ctx);
+        let options = match self.tessellation_options {
+            Some(options) => options,
+            None => return egui_ctx.tessellate(clipped_shapes),
+        };
+        // The context tessellates with its own options, so swap ours in for this call
+        let previous = std::mem::replace(&mut *egui_ctx.tessellation_options(), options);
+        let clipped_meshes = egui_ctx.tessellate(clipped_shapes);
+        *egui_ctx.tessellation_options() = previous;
+        clipped_meshes
+    }
+
+    /// Sets the [`TessellationOptions`] used by [`Painter::tessellate`] and all draw methods
+    /// that take shapes, instead of the options of the egui context,
+    /// e.g. to turn off feathering or paint clip rects with `debug_paint_clip_rects`.
+    /// `None` (the default) uses the context's options.
+    ///
+    /// `pixels_per_point` and `aa_size` are still set by the context from its scale.
+    /// Meshes tessellated by the caller and drawn with [`Painter::draw_meshes`] aren't affected.
+    pub fn set_tessellation_options(&mut self, options: Option<TessellationOptions>) {
+        self.tessellation_options = options;
+    }
+
+    /// Advances to the next rendering subpass and draws already tessellated meshes,
+    /// see [`Painter::tessellate`].
+    /// Like [`Painter::draw`], this draws nothing if `window_size_points` is zero or not finite.
+    ///
+    /// Doesn't need the egui [`Context`]: the meshes are drawn with the `pixels_per_point` of
+    /// the context last passed to [`Painter::tessellate`], unless overridden with
+    /// [`Painter::set_pixels_per_point`].
+    /// To tessellate on another thread, e.g. while the previous frame is submitted,
+    /// call [`Context::tessellate`] there and set the scale with
+    /// [`Painter::set_pixels_per_point`] before drawing the meshes.
+    pub fn draw_meshes<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+        clipped_meshes: &[ClippedMesh],
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        builder.next_subpass(Inline)?;
+        self.draw_meshes_in_current_subpass(builder, window_size_points, clipped_meshes)
+    }
+
+    /// Like [`Painter::draw_meshes`], but draws in the current subpass
+    /// instead of advancing to the next one first.
+    pub fn draw_meshes_in_current_subpass<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+        clipped_meshes: &[ClippedMesh],
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let pipeline = self.pipeline.clone();
+        self.record_draw(builder, pipeline, window_size_points, clipped_meshes)
+    }
+
+    /// Writes already tessellated meshes to the vertex and index buffers of the next
+    /// [`Painter::draw_uploaded`]. Has to be called outside of a render pass,
+    /// i.e. before beginning the render pass that draws the gui.
+    ///
+    /// With [`PainterBuilder::device_local_geometry`] this records copying the geometry into
+    /// device local buffers, which [`Painter::draw_meshes`] can't do inside the render pass.
+    pub fn upload_meshes<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        clipped_meshes: &[ClippedMesh],
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        self.uploaded = Some(self.prepare_geometry(builder, clipped_meshes)?);
+        Ok(())
+    }
+
+    /// Advances to the next rendering subpass and draws the meshes of the last
+    /// [`Painter::upload_meshes`]. Draws nothing if no meshes were uploaded since the last draw,
+    /// or if `window_size_points` is zero or not finite.
+    pub fn draw_uploaded<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        builder.next_subpass(Inline)?;
+        let pipeline = self.pipeline.clone();
+        let geometry = self.uploaded.take().unwrap_or_default();
+        self.record_prepared(builder, pipeline, window_size_points, geometry)
+    }
+
+    /// Advances to the next rendering subpass and draws the same geometry as the last draw of
+    /// the selected context, skipping tessellation and the writes to the geometry buffers,
+    /// e.g. on idle frames where the shapes from egui didn't change. Draws nothing if the
+    /// context wasn't drawn yet. Offscreen and cached draws aren't remembered, see [`PaintStats`].
+    ///
+    /// Texture deltas still have to be passed to [`Painter::update_textures`] as usual.
+    /// Draw the shapes again if the font atlas changed, since the texture coordinates of the
+    /// cached geometry would point into the old atlas.
+    /// Works with [`Painter::set_frames_in_flight`], since only the latest geometry is cached
+    /// and its buffers aren't reused until enough new frames were drawn.
+    pub fn draw_unchanged<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        builder.next_subpass(Inline)?;
+        let pipeline = self.pipeline.clone();
+        let geometry = self
+            .last_geometry
+            .get(&self.context)
+            .cloned()
+            .unwrap_or_default();
+        self.record_prepared(builder, pipeline, window_size_points, geometry)
+    }
+
+    /// Tessellates the shapes and writes them to buffers once,
+    /// so they can be drawn into several targets with [`Painter::draw_prepared`],
+    /// e.g. the swapchain and a capture image, without tessellating or uploading them again.
+    /// Has to be called outside of a render pass, like [`Painter::upload_meshes`].
+    ///
+    /// The frame has to be drawn with the same context selected.
+    /// Textures freed by this frame's [`TexturesDelta`] stay available to every draw of it
+    /// and are released by the next [`Painter::update_textures`].
+    pub fn prepare<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Result<PreparedFrame, DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        let geometry = self.prepare_geometry(builder, &clipped_meshes)?;
+        Ok(PreparedFrame { geometry })
+    }
+
+    /// Advances to the next rendering subpass and draws a frame from [`Painter::prepare`].
+    /// Can be called any number of times per frame, with the same or different builders,
+    /// as long as the builder that recorded [`Painter::prepare`] is submitted first.
+    /// Like [`Painter::draw`], this draws nothing if `window_size_points` is zero or not finite.
+    pub fn draw_prepared<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+        frame: &PreparedFrame,
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        builder.next_subpass(Inline)?;
+        self.draw_prepared_in_current_subpass(builder, window_size_points, frame)
+    }
+
+    /// Draws a frame from [`Painter::prepare`] in the current subpass,
+    /// see [`Painter::draw_prepared`].
+    pub fn draw_prepared_in_current_subpass<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_points: [f32; 2],
+        frame: &PreparedFrame,
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let pipeline = self.pipeline.clone();
+        let geometry = frame.geometry.clone();
+        self.record_tracked(builder, pipeline, window_size_points, geometry)
+    }
+
+    /// Draws the meshes in the current subpass using the given pipeline
+    fn record_draw<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        pipeline: Arc<GraphicsPipeline>,
+        window_size_points: [f32; 2],
+        clipped_meshes: &[ClippedMesh],
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let geometry = self.prepare_geometry(builder, clipped_meshes)?;
+        self.record_prepared(builder, pipeline, window_size_points, geometry)
+    }
+
+    /// Merges the meshes into draw calls and writes their geometry to buffers
+    fn prepare_geometry<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        clipped_meshes: &[ClippedMesh],
+    ) -> Result<PreparedGeometry, DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let (mut geometry, triangles) = self.merge_meshes(clipped_meshes)?;
+        // Don't allocate buffers if there's nothing to render
+        if !geometry.batches.is_empty() {
+            geometry.buffers = Some(self.create_buffers(builder, triangles)?);
+        }
+        Ok(geometry)
+    }
+
+    /// Merges the meshes into draw calls, returning their geometry without buffers
+    /// and the vertices and indices to write to them
+    fn merge_meshes(
+        &self,
+        clipped_meshes: &[ClippedMesh],
+    ) -> Result<(PreparedGeometry, (Vec<Vertex>, Vec<u32>)), DrawError> {
+        let num_meshes = clipped_meshes.len();
+        let mut verts = Vec::<Vertex>::with_capacity(num_meshes * 4);
+        let mut batches = Vec::<Batch>::with_capacity(num_meshes);
+        // Indices of each batch, concatenated once all meshes are merged
+        let mut batch_indices = Vec::<Vec<u32>>::with_capacity(num_meshes);
+
+        for cm in clipped_meshes.iter() {
+            let (clip, mesh) = (cm.0, &cm.1);
+
+            // Skip empty meshes
+            if mesh.vertices.len() == 0 || mesh.indices.len() == 0 {
+                continue;
+            }
+
+            #[cfg(feature = "validate-meshes")]
+            validate_mesh(mesh)?;
+
+            let bounds = if self.batch_reordering {
+                mesh.calc_bounds().intersect(clip)
+            } else {
+                clip
+            };
+
+            // Merge with an earlier mesh if they share clip rect and texture,
+            // rebasing the indices onto the vertices of the merged draw call
+            let (batch, base) = match merge_target(
+                &batches,
+                clip,
+                mesh.texture_id,
+                bounds,
+                self.batch_reordering,
+            ) {
+                Some(target) => {
+                    let batch = &mut batches[target];
+                    batch.index_count += mesh.indices.len() as u32;
+                    batch.bounds = batch.bounds.union(bounds);
+                    (target, verts.len() as u32 - batch.vertex_offset as u32)
+                }
+                None => {
+                    batches.push(Batch {
+                        clip,
+                        bounds,
+                        texture_id: mesh.texture_id,
+                        first_index: 0,
+                        index_count: mesh.indices.len() as u32,
+                        vertex_offset: verts.len() as i32,
+                    });
+                    batch_indices.push(Vec::with_capacity(mesh.indices.len()));
+                    (batches.len() - 1, 0)
+                }
+            };
+
+            verts.extend_from_slice(bytemuck::cast_slice(&mesh.vertices));
+            batch_indices[batch].extend(mesh.indices.iter().map(|i| i + base));
+        }
+
+        let mut indices = Vec::<u32>::with_capacity(batch_indices.iter().map(Vec::len).sum());
+        for (batch, batch_indices) in batches.iter_mut().zip(batch_indices) {
+            batch.first_index = indices.len() as u32;
+            indices.extend(batch_indices);
+        }
+
+        let geometry = PreparedGeometry {
+            meshes: num_meshes,
+            vertices: verts.len(),
+            indices: indices.len(),
+            batches,
+            buffers: None,
+        };
+        Ok((geometry, (verts, indices)))
+    }
+
+    /// Records the draw calls of prepared geometry in the current subpass,
+    /// then releases the textures freed by this frame
+    fn record_prepared<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        pipeline: Arc<GraphicsPipeline>,
+        window_size_points: [f32; 2],
+        geometry: PreparedGeometry,
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        self.record_tracked(builder, pipeline, window_size_points, geometry)?;
+        self.free_textures();
+        Ok(())
+    }
+
+    /// Records the draw calls of prepared geometry drawn into the window in the current subpass,
+    /// remembering it for [`Painter::draw_unchanged`] and [`Painter::last_stats`]
+    fn record_tracked<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        pipeline: Arc<GraphicsPipeline>,
+        window_size_points: [f32; 2],
+        geometry: PreparedGeometry,
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        self.last_geometry.insert(self.context, geometry.clone());
+        self.stats = PaintStats {
+            meshes: geometry.meshes,
+            draw_calls: 0,
+            vertices: geometry.vertices,
+            indices: geometry.indices,
+            texture_bytes_uploaded: std::mem::take(&mut self.texture_bytes_uploaded),
+            texture_uploads_skipped: std::mem::take(&mut self.texture_uploads_skipped),
+        };
+        self.stats.draw_calls =
+            self.record_geometry(builder, pipeline, window_size_points, geometry)?;
+        Ok(())
+    }
+
+    /// Records the draw calls of prepared geometry in the current subpass,
+    /// into a primary or secondary command buffer, and returns how many were recorded
+    fn record_geometry<L, P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L, P>,
+        pipeline: Arc<GraphicsPipeline>,
+        window_size_points: [f32; 2],
+        geometry: PreparedGeometry,
+    ) -> Result<usize, DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        #[cfg(feature = "puffin")]
+        puffin::profile_scope!("draw");
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "draw",
+            meshes = geometry.meshes,
+            batches = geometry.batches.len()
+        )
+        .entered();
+
+        // Pushed textures are drawn with a variant whose set 0 is a push descriptor set,
+        // other textures, e.g. a font set of the user's, with the pipeline itself
+        let push_pipeline = self.push_descriptor_pipeline(&pipeline)?;
+        let pipeline = match self.wireframe_pipeline(&pipeline)? {
+            Some(wireframe) => wireframe,
+            None => pipeline,
+        };
+        builder.bind_pipeline_graphics(pipeline.clone());
+
+        // Return if there's nothing to render, or nowhere to render it to (e.g. a minimized window),
+        // where the vertex shader would divide by a degenerate screen size
+        let (vertex_buf, index_buf) = match geometry.buffers {
+            Some(buffers) if is_drawable_size(window_size_points) => buffers,
+            _ => return Ok(0),
+        };
+
+        // Textures missing later on, e.g. after `reset_resources`, are skipped instead
+        if !self.updated_contexts.contains(&self.context) {
+            let missing = geometry.batches.iter().find(|batch| {
+                self.texture_set(batch.texture_id).is_none()
+                    && self.pushed_texture(batch.texture_id).is_none()
+            });
+            if let Some(batch) = missing {
+                return Err(DrawError::TexturesNotInitialized {
+                    texture_id: batch.texture_id,
+                });
+            }
+        }
+
+        self.bind_user_descriptor_set(builder, &pipeline);
+
+        let push_constants = shaders::vs::ty::PushConstants {
+            transform: mul_mat4(
+                rotation_mat4(quarter_turns(self.surface_transform)),
+                mul_mat4(
+                    self.transform.unwrap_or(IDENTITY),
+                    self.ui_transform.map_or(IDENTITY, |ui_transform| {
+                        affine_in_clip_space(ui_transform, window_size_points)
+                    }),
+                ),
+            ),
+            screen_size: window_size_points,
+            flags: (self.vertex_color_mode == VertexColorMode::Linear) as u32 * FLAG_LINEAR_COLORS
+                | self.pixel_snapping as u32 * FLAG_SNAP_TO_PIXELS,
+            pixels_per_point: self.pixels_per_point,
+            white_level: self.sdr_white_level,
+            time_seconds: self.time_seconds,
+            frame_index: self.frame_index,
+            view_offset_scale: self
+                .view_offsets
+                .map(|view| [view.offset[0], view.offset[1], view.scale[0], view.scale[1]]),
+            texture_index: 0,
+        };
+        // Textures with a slot in the texture array are drawn with the bindless pipeline,
+        // unless every texture is drawn with its own set in wireframe mode
+        let bindless = if self.wireframe {
+            None
+        } else {
+            self.bindless_state(&pipeline)?
+        };
+        let mut bindless_bound = false;
+        let mut pushed_bound = false;
+
+        // Bind the whole buffers once, each draw call selects its geometry by offset
+        match vertex_buf {
+            VertexBuffer::Host(buffer) => builder.bind_vertex_buffers(0, buffer),
+            VertexBuffer::Pooled(buffer) => builder.bind_vertex_buffers(0, buffer),
+            VertexBuffer::DeviceLocal(buffer) => builder.bind_vertex_buffers(0, buffer),
+        };
+        builder.push_constants(pipeline.layout().clone(), 0, push_constants);
+        match index_buf {
+            IndexBuffer::U16(buffer) => builder.bind_index_buffer(buffer),
+            IndexBuffer::U32(buffer) => builder.bind_index_buffer(buffer),
+            IndexBuffer::PooledU16(buffer) => builder.bind_index_buffer(buffer),
+            IndexBuffer::PooledU32(buffer) => builder.bind_index_buffer(buffer),
+            IndexBuffer::DeviceLocalU16(buffer) => builder.bind_index_buffer(buffer),
+            IndexBuffer::DeviceLocalU32(buffer) => builder.bind_index_buffer(buffer),
+        };
+
+        if let Some(viewport) = &self.target_viewport {
+            builder.set_viewport(0, [viewport.clone()]);
+        }
+
+        // Clip rects don't apply to transformed geometry, so don't clip at all
+        if self.transform.is_some() {
+            builder.set_scissor(0, [Scissor::irrelevant()]);
+        }
+
+        // Only record state changes when they differ from the previous draw call
+        let mut current_scissor = None;
+        let mut current_texture = None;
+        let mut draw_calls = 0;
+
+        for command in self.commands_for(&geometry, window_size_points) {
+            let scissor = command.scissor;
+            let slot = match (&bindless, &self.bindless) {
+                (Some(_), Some(textures)) => textures.slot(self.context, command.texture_id),
+                _ => None,
+            };
+            let pushed = match &push_pipeline {
+                Some(_) => self.pushed_texture(command.texture_id),
+                None => None,
+            };
+            let texture_set = match (slot, &pushed, self.texture_set(command.texture_id)) {
+                (Some(_), _, _) | (None, Some(_), _) => None,
+                (None, None, Some(texture_set)) => Some(texture_set),
+                (None, None, None) => continue, //skip if we don't have a texture
+            };
+
+            if self.transform.is_none()
+                && current_scissor != Some((scissor.origin, scissor.dimensions))
+            {
+                current_scissor = Some((scissor.origin, scissor.dimensions));
+                builder.set_scissor(0, [scissor]);
+            }
+
+            // Switch pipelines when switching between array slots, pushed textures and
+            // per-texture sets, pushing the constants again for the new pipeline
+            let use_bindless = slot.is_some();
+            let use_pushed = pushed.is_some();
+            if use_bindless != bindless_bound || use_pushed != pushed_bound {
+                bindless_bound = use_bindless;
+                pushed_bound = use_pushed;
+                current_texture = None;
+                let pipeline = match (&bindless, &push_pipeline) {
+                    (Some((bindless_pipeline, set)), _) if use_bindless => {
+                        builder.bind_pipeline_graphics(bindless_pipeline.clone());
+                        builder.bind_descriptor_sets(
+                            PipelineBindPoint::Graphics,
+                            bindless_pipeline.layout().clone(),
+                            0,
+                            DescriptorSetWithOffsets::new(
+                                set.clone() as Arc<dyn DescriptorSet + Send + Sync>,
+                                [],
+                            ),
+                        );
+                        bindless_pipeline
+                    }
+                    (_, Some(push_pipeline)) if use_pushed => {
+                        builder.bind_pipeline_graphics(push_pipeline.clone());
+                        push_pipeline
+                    }
+                    _ => {
+                        builder.bind_pipeline_graphics(pipeline.clone());
+                        &pipeline
+                    }
+                };
+                // The user's set isn't compatible across the layouts of set 0
+                if push_pipeline.is_some() {
+                    self.bind_user_descriptor_set(builder, pipeline);
+                }
+                builder.push_constants(pipeline.layout().clone(), 0, push_constants);
+            }
+
+            if current_texture != Some(command.texture_id) {
+                current_texture = Some(command.texture_id);
+                match (texture_set, slot, &bindless) {
+                    (Some(texture_set), _, _) => {
+                        builder.bind_descriptor_sets(
+                            PipelineBindPoint::Graphics,
+                            pipeline.layout().clone(),
+                            0,
+                            DescriptorSetWithOffsets::new(texture_set, []),
+                        );
+                    }
+                    (None, Some(slot), Some((bindless_pipeline, _))) => {
+                        builder.push_constants(
+                            bindless_pipeline.layout().clone(),
+                            TEXTURE_INDEX_OFFSET,
+                            slot,
+                        );
+                    }
+                    _ => {
+                        if let (Some((view, sampler)), Some(push_pipeline)) =
+                            (pushed, &push_pipeline)
+                        {
+                            builder.push_descriptor_set(
+                                PipelineBindPoint::Graphics,
+                                push_pipeline.layout().clone(),
+                                0,
+                                [WriteDescriptorSet::image_view_sampler(0, view, sampler)],
+                            );
+                        }
+                    }
+                }
+            }
+
+            builder.draw_indexed(
+                command.index_count,
+                1,
+                command.first_index,
+                command.vertex_offset,
+                0,
+            )?;
+            draw_calls += 1;
+        }
+
+        if self.debug_clip_rects {
+            self.record_clip_rects(
+                builder,
+                &pipeline,
+                push_pipeline.as_ref(),
+                push_constants,
+                &geometry.batches,
+                window_size_points,
+            )?;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(draw_calls, "recorded draw calls");
+
+        Ok(draw_calls)
+    }
+
+    /// Turns the batches of prepared geometry into draw commands with scissors in framebuffer
+    /// pixels, skipping batches that are clipped away entirely
+    fn commands_for(
+        &self,
+        geometry: &PreparedGeometry,
+        window_size_points: [f32; 2],
+    ) -> Vec<EguiDrawCommand> {
+        let (vertex_buf, index_buf) = match &geometry.buffers {
+            Some(buffers) if is_drawable_size(window_size_points) => buffers,
+            _ => return Vec::new(),
+        };
+        let index_type = if index_buf.is_u16() {
+            IndexType::U16
+        } else {
+            IndexType::U32
+        };
+
+        let mut commands = Vec::with_capacity(geometry.batches.len());
+        for batch in &geometry.batches {
+            // Skip meshes that would be clipped away entirely instead of emitting an empty scissor
+            let clip = match self.ui_transform {
+                Some(ui_transform) => transform_rect(batch.clip, ui_transform),
+                None => batch.clip,
+            };
+            let scissor = match clip_to_scissor(clip, window_size_points, self.pixels_per_point) {
+                Some(scissor) => scissor,
+                None => continue,
+            };
+            let scissor = rotate_scissor(
+                scissor,
+                window_size_points,
+                self.pixels_per_point,
+                quarter_turns(self.surface_transform),
+            );
+            let scissor = match &self.target_viewport {
+                Some(viewport) => match scissor_in_viewport(scissor, viewport) {
+                    Some(scissor) => scissor,
+                    None => continue,
+                },
+                None => scissor,
+            };
+
+            let first_index = batch.first_index as DeviceSize;
+            let first_vertex = batch.vertex_offset as DeviceSize;
+            commands.push(EguiDrawCommand {
+                vertices: vertex_buf.slice(first_vertex..geometry.vertices as DeviceSize),
+                indices: index_buf
+                    .slice(first_index..first_index + batch.index_count as DeviceSize),
+                index_type,
+                index_count: batch.index_count,
+                scissor,
+                texture_id: batch.texture_id,
+                first_index: batch.first_index,
+                vertex_offset: batch.vertex_offset,
+            });
+        }
+        commands
+    }
+
+    /// Write the vertices and indices to chunks of the buffer pools,
+    /// or reuse the buffers of an earlier frame if frames in flight are configured.
+    /// Indices are stored as `u16` if all of them fit.
+    ///
+    /// With device local geometry the buffers are used for staging,
+    /// and copying them into device local buffers is recorded into the builder.
+    fn create_buffers<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        triangles: (Vec<Vertex>, Vec<u32>),
+    ) -> Result<(VertexBuffer, IndexBuffer), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        #[cfg(feature = "puffin")]
+        puffin::profile_scope!("create_buffers");
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "create_buffers",
+            vertices = triangles.0.len(),
+            indices = triangles.1.len(),
+            device_local = self.device_local_geometry
+        )
+        .entered();
+
+        let (vertex_buffer, index_buffer, slot) = self.write_host_buffers(&triangles)?;
+        if !self.device_local_geometry {
+            return Ok((vertex_buffer, index_buffer));
+        }
+
+        let use_u16 = index_buffer.is_u16();
+        let (vertices, indices) = match slot {
+            Some(slot) => {
+                let buffers = self.geometry[slot].as_mut().unwrap();
+                if buffers.device_local.is_none() {
+                    buffers.device_local = Some(create_device_local_buffers(
+                        &self.queue,
+                        buffers.vertex_capacity,
+                        buffers.index_capacity,
+                        use_u16,
+                    )?);
+                }
+                buffers.device_local.clone().unwrap()
+            }
+            None => create_device_local_buffers(
+                &self.queue,
+                triangles.0.len(),
+                triangles.1.len(),
+                use_u16,
+            )?,
+        };
+        copy_geometry(
+            builder,
+            (&vertex_buffer, &index_buffer),
+            (&vertices, &indices),
+        )?;
+        Ok((VertexBuffer::DeviceLocal(vertices), indices))
+    }
+
+    /// Write the vertices and indices to host visible buffers, returning the
+    /// geometry slot they were written to if frames in flight are configured
+    fn write_host_buffers(
+        &mut self,
+        triangles: &(Vec<Vertex>, Vec<u32>),
+    ) -> Result<(VertexBuffer, IndexBuffer, Option<usize>), DrawError> {
+        let use_u16 = triangles.1.iter().all(|&i| i <= u16::MAX as u32);
+
+        if !self.geometry.is_empty() {
+            let slot = self.next_geometry_slot % self.geometry.len();
+            self.next_geometry_slot = slot + 1;
+
+            let reused = match &self.geometry[slot] {
+                Some(buffers) => buffers.write(&triangles.0, &triangles.1, use_u16),
+                None => false,
+            };
+            if !reused {
+                let buffers =
+                    GeometryBuffers::new(self.device.clone(), &triangles.0, &triangles.1, use_u16)?;
+                self.geometry[slot] = Some(buffers);
+            }
+            let buffers = self.geometry[slot].as_ref().unwrap();
+            return Ok((
+                VertexBuffer::Host(buffers.vertices.clone()),
+                buffers.indices.clone(),
+                Some(slot),
+            ));
+        }
+
+        let (vertex_buffer, index_buffer) = self.write_pooled_buffers(triangles)?;
+        Ok((vertex_buffer, index_buffer, None))
+    }
+
+    /// Write the vertices and indices to new chunks of the buffer pools,
+    /// which aren't reused while the returned buffers are alive
+    fn write_pooled_buffers(
+        &mut self,
+        triangles: &(Vec<Vertex>, Vec<u32>),
+    ) -> Result<(VertexBuffer, IndexBuffer), DrawError> {
+        // Pools reuse the memory of chunks the GPU is done with, so this rarely allocates
+        let vertex_buffer =
+            VertexBuffer::Pooled(self.vertex_pool.chunk(triangles.0.iter().cloned())?);
+        let index_buffer = if triangles.1.iter().all(|&i| i <= u16::MAX as u32) {
+            IndexBuffer::PooledU16(
+                self.index_pool_u16
+                    .chunk(triangles.1.iter().map(|&i| i as u16))?,
+            )
+        } else {
+            IndexBuffer::PooledU32(self.index_pool_u32.chunk(triangles.1.iter().copied())?)
+        };
+        Ok((vertex_buffer, index_buffer))
+    }
+}
+
+/// Take the textures retired more than `frames_in_flight` frames before `frame` out of the garbage
+fn collect_retired<T>(garbage: &mut Vec<(u64, T)>, frame: u64, frames_in_flight: usize) -> Vec<T> {
+    let (collected, kept): (Vec<_>, Vec<_>) = std::mem::take(garbage)
+        .into_iter()
+        .partition(|(retired, _)| frame - retired > frames_in_flight as u64);
+    *garbage = kept;
+    collected.into_iter().map(|(_, texture)| texture).collect()
+}
+
+/// Find the batch a mesh can be merged into: the last one if it has the same clip rect and
+/// texture, or with `reorder` an earlier one if the mesh overlaps none of the batches after it
+fn merge_target(
+    batches: &[Batch],
+    clip: Rect,
+    texture_id: TextureId,
+    bounds: Rect,
+    reorder: bool,
+) -> Option<usize> {
+    for (index, batch) in batches.iter().enumerate().rev() {
+        if batch.clip == clip && batch.texture_id == texture_id {
+            return Some(index);
+        }
+        if !reorder || batch.bounds.intersects(bounds) {
+            return None;
+        }
+    }
+    None
+}
+
+/// Check that every index of a mesh is in range and every vertex position is finite
+#[cfg(feature = "validate-meshes")]
+fn validate_mesh(mesh: &egui::epaint::Mesh) -> Result<(), DrawError> {
+    if let Some(&index) = mesh
+        .indices
+        .iter()
+        .find(|&&i| i as usize >= mesh.vertices.len())
+    {
+        return Err(DrawError::InvalidMesh {
+            texture_id: mesh.texture_id,
+            index,
+            vertex_count: mesh.vertices.len(),
+        });
+    }
+    if let Some(vertex) = mesh
+        .vertices
+        .iter()
+        .position(|vertex| !vertex.pos.x.is_finite() || !vertex.pos.y.is_finite())
+    {
+        return Err(DrawError::NonFiniteVertex {
+            texture_id: mesh.texture_id,
+            vertex,
+        });
+    }
+    Ok(())
+}
+
+/// Add the usage needed to copy from a host visible geometry buffer into a device local one
+fn staging_usage(usage: BufferUsage) -> BufferUsage {
+    BufferUsage {
+        transfer_source: true,
+        ..usage
+    }
+}
+
+/// Allocate device local vertex and index buffers with room for the given number of elements
+fn create_device_local_buffers(
+    queue: &Arc<Queue>,
+    vertex_capacity: usize,
+    index_capacity: usize,
+    use_u16: bool,
+) -> Result<(Arc<DeviceLocalBuffer<[Vertex]>>, IndexBuffer), DeviceMemoryAllocError> {
+    let usage = BufferUsage {
+        vertex_buffer: true,
+        transfer_destination: true,
+        ..BufferUsage::none()
+    };
+    let vertices = DeviceLocalBuffer::array(
+        queue.device().clone(),
+        vertex_capacity as _,
+        usage,
+        [queue.family()],
+    )?;
+    let indices = IndexBuffer::new_device_local(queue, index_capacity, use_u16)?;
+    Ok((vertices, indices))
+}
+
+/// Record copying staging buffers into device local buffers of the same size.
+/// Fails if the builder is inside a render pass.
+fn copy_geometry<P>(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+    staging: (&VertexBuffer, &IndexBuffer),
+    device_local: (&Arc<DeviceLocalBuffer<[Vertex]>>, &IndexBuffer),
+) -> Result<(), CopyBufferError>
+where
+    P: CommandPoolBuilderAlloc,
+{
+    match staging.0 {
+        VertexBuffer::Host(src) => builder.copy_buffer(src.clone(), device_local.0.clone())?,
+        VertexBuffer::Pooled(src) => builder.copy_buffer(src.clone(), device_local.0.clone())?,
+        VertexBuffer::DeviceLocal(_) => unreachable!("staging buffers are host visible"),
+    };
+    match (staging.1, device_local.1) {
+        (IndexBuffer::U16(src), IndexBuffer::DeviceLocalU16(dst)) => {
+            builder.copy_buffer(src.clone(), dst.clone())?
+        }
+        (IndexBuffer::PooledU16(src), IndexBuffer::DeviceLocalU16(dst)) => {
+            builder.copy_buffer(src.clone(), dst.clone())?
+        }
+        (IndexBuffer::U32(src), IndexBuffer::DeviceLocalU32(dst)) => {
+            builder.copy_buffer(src.clone(), dst.clone())?
+        }
+        (IndexBuffer::PooledU32(src), IndexBuffer::DeviceLocalU32(dst)) => {
+            builder.copy_buffer(src.clone(), dst.clone())?
+        }
+        _ => unreachable!("staging and device local index buffers have the same index type"),
+    };
+    Ok(())
+}
+
+/// Slice the elements in `range` out of a typed buffer
+fn typed_slice<T, B>(buffer: &Arc<B>, range: Range<DeviceSize>) -> Arc<dyn BufferAccess>
+where
+    T: Send + Sync + 'static,
+    B: TypedBufferAccess<Content = [T]> + 'static,
+{
+    BufferSlice::from_typed_buffer_access(buffer.clone())
+        .slice(range)
+        .expect("range inside the buffer")
+}
+
+/// Whether a target of the given size can be drawn to, i.e. it has a finite, positive size
+fn is_drawable_size(size: [f32; 2]) -> bool {
+    size.iter().all(|&x| x.is_finite() && x > 0.0)
+}
+
+/// Scale to draw a context with, the override if there is one
+fn pixels_per_point(pixels_per_point_override: Option<f32>, egui_ctx: &Context) -> f32 {
+    pixels_per_point_override.unwrap_or_else(|| egui_ctx.pixels_per_point())
+}
+
+/// Convert a clip rect in points into a scissor in pixels clamped to the target size,
+/// or `None` if the scissor would have zero width or height.
+///
+/// The min corner is rounded down and the max corner up, so partially covered pixels
+/// at the edges aren't cut off, e.g. a clip rect from 0.0 to 10.5 covers 11 pixels.
+fn clip_to_scissor(clip: Rect, target_size: [f32; 2], pixels_per_point: f32) -> Option<Scissor> {
+    let (width, height) = (
+        (target_size[0] * pixels_per_point).ceil(),
+        (target_size[1] * pixels_per_point).ceil(),
+    );
+    let min_x = (clip.min.x * pixels_per_point).floor().clamp(0.0, width) as u32;
+    let min_y = (clip.min.y * pixels_per_point).floor().clamp(0.0, height) as u32;
+    let max_x = (clip.max.x * pixels_per_point).ceil().clamp(0.0, width) as u32;
+    let max_y = (clip.max.y * pixels_per_point).ceil().clamp(0.0, height) as u32;
+
+    let (w, h) = (max_x.saturating_sub(min_x), max_y.saturating_sub(min_y));
+    if w == 0 || h == 0 {
+        return None;
+    }
+
+    Some(Scissor {
+        origin: [min_x, min_y],
+        dimensions: [w, h],
+    })
+}
+
+/// Move a scissor relative to the target viewport into the framebuffer, clamped to the viewport,
+/// or `None` if none of it is inside the viewport
+fn scissor_in_viewport(scissor: Scissor, viewport: &Viewport) -> Option<Scissor> {
+    let [x, y] = viewport.origin.map(|side| side.round() as u32);
+    let right = (viewport.origin[0] + viewport.dimensions[0]).round() as u32;
+    let bottom = (viewport.origin[1] + viewport.dimensions[1]).round() as u32;
+    let min_x = (x + scissor.origin[0]).min(right);
+    let min_y = (y + scissor.origin[1]).min(bottom);
+    let max_x = (x + scissor.origin[0] + scissor.dimensions[0]).min(right);
+    let max_y = (y + scissor.origin[1] + scissor.dimensions[1]).min(bottom);
+
+    let (w, h) = (max_x - min_x, max_y - min_y);
+    if w == 0 || h == 0 {
+        return None;
+    }
+
+    Some(Scissor {
+        origin: [min_x, min_y],
+        dimensions: [w, h],
+    })
+}
+
+/// Viewport covering the whole framebuffer of a window,
+/// with the sides swapped for a swapchain pre-rotated by an odd number of quarter turns
+fn window_viewport(
+    window_size_points: [f32; 2],
+    pixels_per_point: f32,
+    quarter_turns: u32,
+) -> Viewport {
+    let [width, height] = window_size_points.map(|side| side * pixels_per_point);
+    let dimensions = if quarter_turns % 2 == 1 {
+        [height, width]
+    } else {
+        [width, height]
+    };
+    Viewport {
+        origin: [0.0, 0.0],
+        dimensions,
+        depth_range: 0.0..1.0,
+    }
+}
+
+/// Number of clockwise quarter turns of a pre-rotated surface
+fn quarter_turns(transform: SurfaceTransform) -> u32 {
+    match transform {
+        SurfaceTransform::Rotate90 => 1,
+        SurfaceTransform::Rotate180 => 2,
+        SurfaceTransform::Rotate270 => 3,
+        _ => 0,
+    }
+}
+
+/// Column major matrix rotating clip space positions by clockwise quarter turns
+fn rotation_mat4(quarter_turns: u32) -> [[f32; 4]; 4] {
+    let (sin, cos) = match quarter_turns % 4 {
+        0 => (0.0, 1.0),
+        1 => (1.0, 0.0),
+        2 => (0.0, -1.0),
+        _ => (-1.0, 0.0),
+    };
+    [
+        [cos, sin, 0.0, 0.0],
+        [-sin, cos, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+/// Multiplies two column major matrices
+fn mul_mat4(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut result = [[0.0; 4]; 4];
+    for (column, result_column) in result.iter_mut().enumerate() {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+/// Convert a 2D affine transform of positions in points into the equivalent transform of
+/// clip space positions, which the vertex shader applies after mapping points to clip space
+fn affine_in_clip_space(affine: [[f32; 3]; 3], window_size_points: [f32; 2]) -> [[f32; 4]; 4] {
+    let (sx, sy) = (2.0 / window_size_points[0], 2.0 / window_size_points[1]);
+    let to_clip_space = [
+        [sx, 0.0, 0.0, 0.0],
+        [0.0, sy, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [-1.0, -1.0, 0.0, 1.0],
+    ];
+    let to_points = [
+        [1.0 / sx, 0.0, 0.0, 0.0],
+        [0.0, 1.0 / sy, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [1.0 / sx, 1.0 / sy, 0.0, 1.0],
+    ];
+    let affine = [
+        [affine[0][0], affine[0][1], 0.0, 0.0],
+        [affine[1][0], affine[1][1], 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [affine[2][0], affine[2][1], 0.0, 1.0],
+    ];
+    mul_mat4(to_clip_space, mul_mat4(affine, to_points))
+}
+
+/// Transform a rect with a 2D affine transform, returning the bounding box of its corners
+fn transform_rect(rect: Rect, affine: [[f32; 3]; 3]) -> Rect {
+    let transform = |p: egui::Pos2| {
+        egui::pos2(
+            affine[0][0] * p.x + affine[1][0] * p.y + affine[2][0],
+            affine[0][1] * p.x + affine[1][1] * p.y + affine[2][1],
+        )
+    };
+    Rect::from_points(&[
+        transform(rect.left_top()),
+        transform(rect.right_top()),
+        transform(rect.left_bottom()),
+        transform(rect.right_bottom()),
+    ])
+}
+
+/// Rotate a scissor of the unrotated window into the framebuffer of a pre-rotated surface.
+/// The window covers partial pixels at its far edges like [`clip_to_scissor`],
+/// so scissors that reach them are rotated without losing their last row or column.
+fn rotate_scissor(
+    scissor: Scissor,
+    window_size_points: [f32; 2],
+    pixels_per_point: f32,
+    quarter_turns: u32,
+) -> Scissor {
+    let width = (window_size_points[0] * pixels_per_point).ceil() as u32;
+    let height = (window_size_points[1] * pixels_per_point).ceil() as u32;
+    let [x, y] = scissor.origin;
+    let [w, h] = scissor.dimensions;
+    let (origin, dimensions) = match quarter_turns % 4 {
+        0 => return scissor,
+        1 => ([height.saturating_sub(y + h), x], [h, w]),
+        2 => (
+            [width.saturating_sub(x + w), height.saturating_sub(y + h)],
+            [w, h],
+        ),
+        _ => ([y, width.saturating_sub(x + w)], [h, w]),
+    };
+    Scissor { origin, dimensions }
+}
+
+/// Check that a pipeline created outside of the painter can draw its vertices,
+/// push constants and textures
+fn validate_pipeline(pipeline: &GraphicsPipeline) -> Result<(), PainterCreationError> {
+    let incompatible = |expected: String, found: String| {
+        Err(PainterCreationError::IncompatiblePipeline { expected, found })
+    };
+
+    let vertex_input = pipeline.vertex_input_state();
+    let stride = std::mem::size_of::<Vertex>() as u32;
+    match vertex_input.bindings.get(&0) {
+        Some(binding) if binding.stride == stride => (),
+        Some(binding) => {
+            return incompatible(
+                format!("a stride of {} bytes in vertex binding 0", stride),
+                format!("a stride of {} bytes", binding.stride),
+            )
+        }
+        None => return incompatible("vertex binding 0".to_owned(), "no binding 0".to_owned()),
+    }
+    // pos, uv and color of `Vertex`
+    let attributes = [
+        (0, 0, Format::R32G32_SFLOAT),
+        (1, 8, Format::R32G32_SFLOAT),
+        (2, 16, Format::R32_UINT),
+    ];
+    for (location, offset, format) in attributes {
+        let expected = format!(
+            "vertex attribute {} in binding 0 at offset {} with format {:?}",
+            location, offset, format
+        );
+        match vertex_input.attributes.get(&location) {
+            Some(attribute)
+                if attribute.binding == 0
+                    && attribute.offset == offset
+                    && attribute.format == format => {}
+            Some(attribute) => {
+                return incompatible(
+                    expected,
+                    format!(
+                        "binding {} at offset {} with format {:?}",
+                        attribute.binding, attribute.offset, attribute.format
+                    ),
+                )
+            }
+            None => return incompatible(expected, format!("no attribute {}", location)),
+        }
+    }
+
+    let layout = pipeline.layout();
+    let size = std::mem::size_of::<shaders::vs::ty::PushConstants>() as u32;
+    let covers_push_constants = layout
+        .push_constant_ranges()
+        .iter()
+        .any(|range| range.stages.vertex && range.offset == 0 && range.size >= size);
+    if !covers_push_constants {
+        return incompatible(
+            format!("{} bytes of push constants in the vertex stage", size),
+            format!("the ranges {:?}", layout.push_constant_ranges()),
+        );
+    }
+    if layout.descriptor_set_layouts().is_empty() {
+        return incompatible(
+            "a texture descriptor set in set 0".to_owned(),
+            "no descriptor sets".to_owned(),
+        );
+    }
+    Ok(())
+}
+
+/// Check that the gui can be drawn in the subpass, before creating a pipeline for it
+fn validate_subpass(
+    subpass: &Subpass,
+    color_space: OutputColorSpace,
+) -> Result<(), PainterCreationError> {
+    let incompatible = |expected: &str, found: String| {
+        Err(PainterCreationError::IncompatibleSubpass {
+            expected: expected.to_owned(),
+            found,
+        })
+    };
+
+    let desc = subpass.render_pass().desc();
+    let subpass_desc = &desc.subpasses()[subpass.index() as usize];
+    if subpass_desc.color_attachments.is_empty() {
+        let found = match subpass_desc.depth_stencil {
+            Some(_) => "only a depth/stencil attachment",
+            None => "no attachments",
+        };
+        return incompatible(
+            "at least one color attachment",
+            format!("{} in subpass {}", found, subpass.index()),
+        );
+    }
+
+    if subpass_desc.view_mask != 0 && !subpass.render_pass().device().enabled_features().multiview {
+        return Err(PainterCreationError::MissingFeature {
+            feature: "multiview",
+        });
+    }
+
+    if subpass_desc.view_mask >> 2 != 0 {
+        return incompatible(
+            "a view mask of views 0 and 1 at most",
+            format!(
+                "the view mask {:#b} in subpass {}",
+                subpass_desc.view_mask,
+                subpass.index()
+            ),
+        );
+    }
+
+    for &(attachment, _) in &subpass_desc.color_attachments {
+        let attachment_desc = &desc.attachments()[attachment];
+        match attachment_desc.format.type_color() {
+            Some(NumericType::UINT) | Some(NumericType::SINT) => {
+                return incompatible(
+                    "color attachments with a format that can be blended",
+                    format!(
+                        "the integer format {:?} in attachment {}",
+                        attachment_desc.format, attachment
+                    ),
+                )
+            }
+            None => {
+                return incompatible(
+                    "color attachments with a color format",
+                    format!(
+                        "the non-color format {:?} in attachment {}",
+                        attachment_desc.format, attachment
+                    ),
+                )
+            }
+            Some(NumericType::SFLOAT) | Some(NumericType::UFLOAT) => (),
+            _ if color_space == OutputColorSpace::ExtendedSrgbLinear => {
+                return incompatible(
+                    "color attachments with a float format for extended linear sRGB",
+                    format!(
+                        "the format {:?} in attachment {}",
+                        attachment_desc.format, attachment
+                    ),
+                )
+            }
+            _ => (),
+        }
+        if attachment_desc.samples != SampleCount::Sample1 {
+            return incompatible(
+                "color attachments with one sample per pixel",
+                format!(
+                    "{:?} in color attachment {}",
+                    attachment_desc.samples, attachment
+                ),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Bits of the `flags` push constant, have to match `FLAG_*` in `shaders/vert.vert`
+const FLAG_LINEAR_COLORS: u32 = 1;
+const FLAG_SNAP_TO_PIXELS: u32 = 2;
+
+/// Offset of `texture_index`, the last member of the push constants,
+/// which is pushed on its own before each draw call with bindless textures
+const TEXTURE_INDEX_OFFSET: u32 =
+    (std::mem::size_of::<shaders::vs::ty::PushConstants>() - std::mem::size_of::<u32>()) as u32;
+
+/// Variations of the pipeline for the same subpass, created on demand
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+struct PipelineVariant {
+    /// Sample the texture array, see [`PainterBuilder::bindless_textures`]
+    bindless: bool,
+    /// Draw the edges of the triangles only, see [`Painter::set_wireframe`]
+    wireframe: bool,
+    /// Push the texture instead of binding a set, see [`PainterBuilder::use_push_descriptors`]
+    push_descriptors: bool,
+}
+
+/// Creates the graphics pipeline the painter draws with, e.g. to change one of its settings
+/// and pass it to [`Painter::with_pipeline`].
+///
+/// The subpass has to be one the gui can be drawn in, see [`PainterCreationError::IncompatibleSubpass`].
+pub fn create_pipeline(
+    device: Arc<Device>,
+    subpass: Subpass,
+    config: PipelineConfig,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    create_pipeline_variant(device, subpass, config, PipelineVariant::default(), None)
+}
+
+/// Create a graphics pipeline with the shaders and settings necessary to render egui output
+fn create_pipeline_variant(
+    device: Arc<Device>,
+    subpass: Subpass,
+    config: PipelineConfig,
+    variant: PipelineVariant,
+    custom: Option<&ShaderModules>,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    // Multiview subpasses need a shader that reads the view index
+    let desc = subpass.render_pass().desc();
+    let vs = match custom.and_then(|custom| custom.vertex.clone()) {
+        Some(vs) => vs,
+        None if desc.subpasses()[subpass.index() as usize].view_mask != 0 => {
+            shaders::vs_multiview::load(device.clone()).unwrap()
+        }
+        None => shaders::vs::load(device.clone()).unwrap(),
+    };
+    let fs = if variant.bindless {
+        shaders::fs_bindless::load(device.clone())
+    } else {
+        shaders::fs::load(device.clone())
+    }
+    .unwrap();
+
+    // Both fragment shaders have the same specialization constants
+    let fs_constants = shaders::fs::SpecializationConstants {
+        encode_srgb: (config.color_space == OutputColorSpace::EncodeSrgbInShader) as u32,
+    };
+
+    // Only state every device supports: no depth clamp or bias, and lines of width 1,
+    // which don't need `wide_lines` even when drawing wireframes
+    let mut rasterization = RasterizationState::new().cull_mode(config.cull_mode);
+    rasterization.depth_clamp_enable = false;
+    rasterization.depth_bias = None;
+    rasterization.line_width = StateMode::Fixed(1.0);
+    if variant.wireframe {
+        rasterization.polygon_mode = PolygonMode::Line;
+    }
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        // Triangle lists without primitive restart
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+        .rasterization_state(rasterization)
+        .color_blend_state(
+            ColorBlendState::new(subpass.num_color_attachments()).blend(config.blend),
+        )
+        .render_pass(subpass);
+    // Set 0 holds the texture of each draw call, which is pushed instead of bound for that variant
+    let set_layouts = |sets: &mut [DescriptorSetDesc]| {
+        if variant.push_descriptors {
+            sets[0].set_push_descriptor(true);
+        }
+    };
+    // Custom fragment shaders get no specialization constants
+    let pipeline = match custom.and_then(|custom| custom.fragment.as_ref()) {
+        Some(custom_fs) => pipeline
+            .fragment_shader(custom_fs.entry_point("main").unwrap(), ())
+            .with_auto_layout(device.clone(), set_layouts)?,
+        None => pipeline
+            .fragment_shader(fs.entry_point("main").unwrap(), fs_constants)
+            .with_auto_layout(device.clone(), set_layouts)?,
+    };
+    Ok(pipeline)
+}
+
+/// Creates a sampler for the gui's textures like the painter does,
+/// e.g. to pass it to [`Painter::with_pipeline`].
+/// Anisotropy and LOD bias are limited to what the device supports.
+pub fn create_sampler(
+    device: Arc<Device>,
+    config: SamplerConfig,
+) -> Result<Arc<Sampler>, SamplerCreationError> {
+    let SamplerConfig {
+        texture_options: options,
+        filter,
+    } = config;
+    // Anisotropy can only be used if the feature is enabled, otherwise fall back to none
+    let anisotropy = if device.enabled_features().sampler_anisotropy {
+        let limit = device.physical_device().properties().max_sampler_anisotropy;
+        Some(
+            options
+                .anisotropy
+                .map_or(1.0, |level| (level as f32).clamp(1.0, limit)),
+        )
+    } else {
+        None
+    };
+    // Mipmapped textures may use all of their levels, others only have one
+    let max_lod = if options.mipmaps { 1000.0 } else { 0.0 };
+
+    let max_lod_bias = device.physical_device().properties().max_sampler_lod_bias;
+    let lod_bias = options.lod_bias.clamp(-max_lod_bias, max_lod_bias);
+
+    let mipmap_mode = match filter {
+        Filter::Nearest => SamplerMipmapMode::Nearest,
+        _ => SamplerMipmapMode::Linear,
+    };
+
+    Sampler::start(device.clone())
+        .mag_filter(filter)
+        .min_filter(filter)
+        .mipmap_mode(mipmap_mode)
+        .address_mode_u(options.wrap_u.into())
+        .address_mode_v(options.wrap_v.into())
+        .address_mode_w(SamplerAddressMode::ClampToEdge)
+        .mip_lod_bias(lod_bias)
+        .anisotropy(anisotropy)
+        .min_lod(0.0)
+        .max_lod(max_lod)
+        .build()
+}
+
+/// Hash the pixels of an egui texture and the options it's uploaded with,
+/// to tell most changed uploads apart without comparing the pixels.
+/// The hash is only compared within one run, since `DefaultHasher` may change between releases.
+fn hash_upload(image: &ImageData, options: TextureOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.size().hash(&mut hasher);
+    image_format(image).hash(&mut hasher);
+    options.hash(&mut hasher);
+    match image {
+        ImageData::Color(image) => {
+            for color in &image.pixels {
+                hasher.write(&color.to_array());
+            }
+        }
+        ImageData::Alpha(image) => hasher.write(&image.pixels),
+    }
+    hasher.finish()
+}
+
+/// Convert the pixels of an egui texture to RGBA bytes
+fn image_data_bytes(texture: &ImageData) -> Vec<u8> {
+    match texture {
+        ImageData::Color(image) => image.pixels.iter().flat_map(|c| c.to_array()).collect(),
+        ImageData::Alpha(image) => image.pixels.iter().flat_map(|&r| [r, r, r, r]).collect(),
+    }
+}
+
+/// Format of the image for the given egui texture
+fn image_format(texture: &ImageData) -> Format {
+    match texture {
+        ImageData::Color(_) => Format::R8G8B8A8_SRGB,
+        ImageData::Alpha(_) => Format::R8G8B8A8_UNORM,
+    }
+}
+
+/// Write the pixels of a partial delta into the RGBA bytes of an image with the given width
+fn patch_pixels(pixels: &mut [u8], width: usize, delta: &ImageDelta) {
+    let [x, y] = delta.pos.unwrap_or([0, 0]);
+    let row_bytes = delta.image.width() * 4;
+    let delta_pixels = image_data_bytes(&delta.image);
+    for (row, src) in delta_pixels.chunks_exact(row_bytes).enumerate() {
+        let start = ((y + row) * width + x) * 4;
+        pixels[start..start + row_bytes].copy_from_slice(src);
+    }
+}
+
+/// Downsample RGBA pixels to half their size, rounding down to at least one pixel,
+/// averaging the colors of sRGB images in linear space
+fn downsample_pixels(pixels: &[u8], dimensions: [u32; 2], srgb: bool) -> Vec<u8> {
+    let [width, height] = dimensions.map(|side| side as usize);
+    let [half_width, half_height] = dimensions.map(|side| (side as usize / 2).max(1));
+    let to_linear = |value: u8, channel: usize| {
+        if srgb && channel < 3 {
+            linear_f32_from_gamma_u8(value)
+        } else {
+            value as f32 / 255.0
+        }
+    };
+    let from_linear = |value: f32, channel: usize| {
+        if srgb && channel < 3 {
+            gamma_u8_from_linear_f32(value)
+        } else {
+            linear_u8_from_linear_f32(value)
+        }
+    };
+
+    let mut half = Vec::with_capacity(half_width * half_height * 4);
+    for y in 0..half_height {
+        for x in 0..half_width {
+            for channel in 0..4 {
+                let mut sum = 0.0;
+                for [dx, dy] in [[0, 0], [1, 0], [0, 1], [1, 1]] {
+                    let sx = (x * 2 + dx).min(width - 1);
+                    let sy = (y * 2 + dy).min(height - 1);
+                    sum += to_linear(pixels[(sy * width + sx) * 4 + channel], channel);
+                }
+                half.push(from_linear(sum / 4.0, channel));
+            }
+        }
+    }
+    half
+}
+
+/// Create a Vulkano image for the given egui texture
+fn create_image(
+    queue: Arc<Queue>,
+    texture: &ImageData,
+) -> Result<Arc<StorageImage>, ImageCreationError> {
+    create_storage_image(
+        queue,
+        [texture.width() as u32, texture.height() as u32],
+        image_format(texture),
+    )
+}
+
+/// Create a Vulkano image that textures can be uploaded to
+fn create_storage_image(
+    queue: Arc<Queue>,
+    dimensions: [u32; 2],
+    format: Format,
+) -> Result<Arc<StorageImage>, ImageCreationError> {
+    let dimensions = ImageDimensions::Dim2d {
+        width: dimensions[0],
+        height: dimensions[1],
+        array_layers: 1,
+    };
+
+    let usage = ImageUsage {
+        transfer_source: true,
+        transfer_destination: true,
+        sampled: true,
+        storage: false,
+        ..ImageUsage::none()
+    };
 
     let image = StorageImage::with_usage(
         queue.device().clone(),
@@ -434,8 +4141,1160 @@ fn create_image(
         format,
         usage,
         ImageCreateFlags::none(),
-        [queue.family()],
+        // Concurrent sharing, so the uploads can be submitted on any queue
+        queue.device().active_queue_families(),
     )?;
 
     Ok(image)
 }
+
+#[cfg(test)]
+mod tests {
+    use egui::{pos2, Color32, ColorImage};
+    use vulkano::command_buffer::{CommandBufferUsage, SubpassContents};
+    use vulkano::instance::{Instance, InstanceExtensions};
+    use vulkano::sync::{self, GpuFuture};
+    use vulkano::Version;
+
+    use super::*;
+
+    /// Queue of the first device with graphics, with those of the given features it supports
+    /// and its optional extensions. Panics without a Vulkan driver.
+    ///
+    /// The tests that need a device are ignored, so runs without a driver report them as such.
+    /// CI runs them on lavapipe with `cargo test -- --include-ignored`.
+    fn test_queue_with(features: Features) -> Arc<Queue> {
+        let instance = Instance::new(None, Version::V1_1, &InstanceExtensions::none(), None)
+            .expect("tests that need a device need a Vulkan driver, e.g. lavapipe");
+        let (physical, family) = PhysicalDevice::enumerate(&instance)
+            .find_map(|physical| {
+                let family = physical.queue_families().find(|q| q.supports_graphics())?;
+                Some((physical, family))
+            })
+            .expect("no Vulkan device with a graphics queue");
+        let extensions = physical
+            .supported_extensions()
+            .intersection(&optional_device_extensions());
+        let (_, mut queues) = Device::new(
+            physical,
+            &physical.supported_features().intersection(&features),
+            &physical.required_extensions().union(&extensions),
+            [(family, 0.5)],
+        )
+        .unwrap();
+        queues.next().unwrap()
+    }
+
+    fn test_queue() -> Arc<Queue> {
+        test_queue_with(optional_features())
+    }
+
+    /// Subpass with a single sRGB color attachment
+    fn test_subpass(device: &Arc<Device>) -> Subpass {
+        color_subpass(device, Format::R8G8B8A8_SRGB)
+    }
+
+    /// Subpass with one color attachment of the given format
+    fn color_subpass(device: &Arc<Device>, format: Format) -> Subpass {
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+            pass: { color: [color], depth_stencil: {} }
+        )
+        .unwrap();
+        Subpass::from(render_pass, 0).unwrap()
+    }
+
+    /// Subpass with a depth attachment and no color attachments
+    fn depth_only_subpass(device: &Arc<Device>) -> Subpass {
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                depth: {
+                    load: Clear,
+                    store: DontCare,
+                    format: Format::D16_UNORM,
+                    samples: 1,
+                }
+            },
+            pass: { color: [], depth_stencil: {depth} }
+        )
+        .unwrap();
+        Subpass::from(render_pass, 0).unwrap()
+    }
+
+    fn test_painter() -> Painter {
+        let queue = test_queue();
+        let subpass = test_subpass(queue.device());
+        Painter::new(queue.device().clone(), queue, subpass).unwrap()
+    }
+
+    fn test_builder(painter: &Painter) -> AutoCommandBufferBuilder<PrimaryAutoCommandBuffer> {
+        AutoCommandBufferBuilder::primary(
+            painter.device.clone(),
+            painter.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap()
+    }
+
+    /// Delta setting a whole white texture of the given size
+    fn set_delta(texture_id: TextureId, size: [usize; 2]) -> TexturesDelta {
+        let mut textures_delta = TexturesDelta::default();
+        let image = ColorImage::new(size, Color32::WHITE);
+        textures_delta
+            .set
+            .insert(texture_id, ImageDelta::full(image));
+        textures_delta
+    }
+
+    /// Uploads textures and waits for the upload, so they can be rendered offscreen
+    fn upload_textures(painter: &mut Painter, textures_delta: TexturesDelta) {
+        let mut builder = test_builder(painter);
+        painter
+            .update_textures(textures_delta, &mut builder)
+            .unwrap();
+        sync::now(painter.device.clone())
+            .then_execute(painter.queue.clone(), builder.build().unwrap())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+
+    /// Context that ran one empty frame, so its fonts exist for tessellating
+    fn test_context() -> Context {
+        let ctx = Context::default();
+        let _ = ctx.run(egui::RawInput::default(), |_| {});
+        ctx
+    }
+
+    /// What the tests that draw share: a painter whose font atlas is a white pixel,
+    /// and a context with fonts to tessellate [`DrawFixture::shapes`] in a 64 by 64 window
+    struct DrawFixture {
+        painter: Painter,
+        ctx: Context,
+    }
+
+    impl DrawFixture {
+        fn new() -> Self {
+            Self::with_painter(test_painter())
+        }
+
+        fn with_painter(mut painter: Painter) -> Self {
+            upload_textures(&mut painter, set_delta(TextureId::default(), [1, 1]));
+            Self {
+                painter,
+                ctx: test_context(),
+            }
+        }
+
+        /// A white rect from (10, 10) to (50, 30), clipped to the window
+        fn shapes() -> Vec<ClippedShape> {
+            let clip = Rect::from_min_max(pos2(0.0, 0.0), pos2(64.0, 64.0));
+            let rect = Rect::from_min_max(pos2(10.0, 10.0), pos2(50.0, 30.0));
+            vec![ClippedShape(
+                clip,
+                egui::Shape::rect_filled(rect, 0.0, Color32::WHITE),
+            )]
+        }
+    }
+
+    fn batch(clip: Rect, texture_id: TextureId, bounds: Rect) -> Batch {
+        Batch {
+            clip,
+            bounds,
+            texture_id,
+            first_index: 0,
+            index_count: 3,
+            vertex_offset: 0,
+        }
+    }
+
+    fn scissor(clip: Rect, target_size: [f32; 2], pixels_per_point: f32) -> Option<[u32; 4]> {
+        clip_to_scissor(clip, target_size, pixels_per_point).map(|scissor| {
+            let [x, y] = scissor.origin;
+            let [w, h] = scissor.dimensions;
+            [x, y, w, h]
+        })
+    }
+
+    #[test]
+    fn clip_to_scissor_covers_partial_pixels() {
+        let clip = Rect::from_min_max(pos2(0.0, 0.0), pos2(10.5, 10.5));
+        assert_eq!(scissor(clip, [100.0, 100.0], 1.0), Some([0, 0, 11, 11]));
+    }
+
+    #[test]
+    fn clip_to_scissor_rounds_min_down_and_max_up() {
+        // 1.3 * 1.25 = 1.625 and 10.1 * 1.25 = 12.625 pixels
+        let clip = Rect::from_min_max(pos2(1.3, 1.3), pos2(10.1, 10.1));
+        assert_eq!(scissor(clip, [100.0, 100.0], 1.25), Some([1, 1, 12, 12]));
+    }
+
+    #[test]
+    fn clip_to_scissor_skips_empty_clips() {
+        let target = [100.0, 100.0];
+        let zero_width = Rect::from_min_max(pos2(10.0, 0.0), pos2(10.0, 50.0));
+        let zero_height = Rect::from_min_max(pos2(0.0, 20.0), pos2(50.0, 20.0));
+        let off_screen = Rect::from_min_max(pos2(120.0, 120.0), pos2(150.0, 150.0));
+        let above = Rect::from_min_max(pos2(0.0, -50.0), pos2(50.0, -10.0));
+
+        assert_eq!(scissor(zero_width, target, 1.0), None);
+        assert_eq!(scissor(zero_height, target, 1.0), None);
+        assert_eq!(scissor(off_screen, target, 1.0), None);
+        assert_eq!(scissor(above, target, 1.0), None);
+        assert_eq!(scissor(Rect::NOTHING, target, 1.0), None);
+    }
+
+    #[test]
+    fn pixels_per_point_override_replaces_context_scale() {
+        let egui_ctx = Context::default();
+        assert_eq!(pixels_per_point(None, &egui_ctx), 1.0);
+        assert_eq!(pixels_per_point(Some(1.5), &egui_ctx), 1.5);
+    }
+
+    #[test]
+    fn clip_to_scissor_scales_by_pixels_per_point() {
+        let clip = Rect::from_min_max(pos2(10.0, 20.0), pos2(30.0, 40.0));
+        let scale = pixels_per_point(Some(1.5), &Context::default());
+        assert_eq!(scissor(clip, [100.0, 100.0], scale), Some([15, 30, 30, 30]));
+        // The target size is in points too
+        assert_eq!(scissor(clip, [20.0, 100.0], scale), Some([15, 30, 15, 30]));
+    }
+
+    #[test]
+    fn scissor_in_viewport_moves_to_its_origin() {
+        let viewport = Viewport {
+            origin: [100.0, 200.0],
+            dimensions: [300.0, 100.0],
+            depth_range: 0.0..1.0,
+        };
+        let moved = scissor_in_viewport(
+            Scissor {
+                origin: [10, 20],
+                dimensions: [50, 30],
+            },
+            &viewport,
+        )
+        .unwrap();
+        assert_eq!((moved.origin, moved.dimensions), ([110, 220], [50, 30]));
+    }
+
+    #[test]
+    fn scissor_in_viewport_is_clamped_to_it() {
+        let viewport = Viewport {
+            origin: [100.0, 200.0],
+            dimensions: [300.0, 100.0],
+            depth_range: 0.0..1.0,
+        };
+        let clamped = scissor_in_viewport(
+            Scissor {
+                origin: [280, 0],
+                dimensions: [50, 150],
+            },
+            &viewport,
+        )
+        .unwrap();
+        assert_eq!(
+            (clamped.origin, clamped.dimensions),
+            ([380, 200], [20, 100])
+        );
+
+        let outside = Scissor {
+            origin: [300, 0],
+            dimensions: [50, 50],
+        };
+        assert!(scissor_in_viewport(outside, &viewport).is_none());
+    }
+
+    /// 2D affine transform scaling by `scale` and translating by `offset`, column major
+    fn affine(scale: f32, offset: [f32; 2]) -> [[f32; 3]; 3] {
+        [
+            [scale, 0.0, 0.0],
+            [0.0, scale, 0.0],
+            [offset[0], offset[1], 1.0],
+        ]
+    }
+
+    /// Position of a clip space point transformed by a column major matrix
+    fn apply(matrix: [[f32; 4]; 4], point: [f32; 2]) -> [f32; 2] {
+        [0, 1].map(|row| matrix[0][row] * point[0] + matrix[1][row] * point[1] + matrix[3][row])
+    }
+
+    fn assert_near(found: [f32; 2], expected: [f32; 2]) {
+        let near = (0..2).all(|i| (found[i] - expected[i]).abs() < 1e-5);
+        assert!(near, "expected {:?}, found {:?}", expected, found);
+    }
+
+    #[test]
+    fn mul_mat4_identity_and_translation() {
+        let translation = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.5, -0.5, 0.0, 1.0],
+        ];
+        assert_eq!(mul_mat4(IDENTITY, translation), translation);
+        assert_eq!(mul_mat4(translation, IDENTITY), translation);
+        assert_near(
+            apply(mul_mat4(translation, translation), [0.0, 0.0]),
+            [1.0, -1.0],
+        );
+    }
+
+    #[test]
+    fn affine_in_clip_space_identity() {
+        let matrix = affine_in_clip_space(affine(1.0, [0.0, 0.0]), [200.0, 100.0]);
+        for point in [[-1.0, -1.0], [0.0, 0.0], [0.5, -0.25], [1.0, 1.0]] {
+            assert_near(apply(matrix, point), point);
+        }
+    }
+
+    #[test]
+    fn affine_in_clip_space_translation_and_scale() {
+        // The top left corner moves by 100 points, half of the window
+        let translated = affine_in_clip_space(affine(1.0, [100.0, 100.0]), [200.0, 200.0]);
+        assert_near(apply(translated, [-1.0, -1.0]), [0.0, 0.0]);
+
+        // The center at 100 points moves to 2 * 100 + 50 = 250 points
+        let scaled = affine_in_clip_space(affine(2.0, [50.0, 50.0]), [200.0, 200.0]);
+        assert_near(apply(scaled, [0.0, 0.0]), [1.5, 1.5]);
+    }
+
+    #[test]
+    fn transform_rect_of_clip_rects() {
+        let clip = Rect::from_min_max(pos2(10.0, 10.0), pos2(20.0, 30.0));
+        assert_eq!(transform_rect(clip, affine(1.0, [0.0, 0.0])), clip);
+        assert_eq!(
+            transform_rect(clip, affine(1.0, [100.0, 50.0])),
+            Rect::from_min_max(pos2(110.0, 60.0), pos2(120.0, 80.0))
+        );
+        assert_eq!(
+            transform_rect(clip, affine(2.0, [100.0, 100.0])),
+            Rect::from_min_max(pos2(120.0, 120.0), pos2(140.0, 160.0))
+        );
+    }
+
+    #[cfg(feature = "validate-meshes")]
+    fn triangle() -> egui::epaint::Mesh {
+        let mut mesh = egui::epaint::Mesh::default();
+        mesh.colored_vertex(pos2(0.0, 0.0), egui::Color32::WHITE);
+        mesh.colored_vertex(pos2(10.0, 0.0), egui::Color32::WHITE);
+        mesh.colored_vertex(pos2(0.0, 10.0), egui::Color32::WHITE);
+        mesh.add_triangle(0, 1, 2);
+        mesh
+    }
+
+    #[cfg(feature = "validate-meshes")]
+    #[test]
+    fn validate_mesh_rejects_out_of_range_index() {
+        assert!(validate_mesh(&triangle()).is_ok());
+
+        let mut mesh = triangle();
+        mesh.add_triangle(0, 2, 3);
+        assert!(matches!(
+            validate_mesh(&mesh),
+            Err(DrawError::InvalidMesh {
+                index: 3,
+                vertex_count: 3,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(feature = "validate-meshes")]
+    #[test]
+    fn validate_mesh_rejects_nan_vertex() {
+        let mut mesh = triangle();
+        mesh.vertices[1].pos.y = f32::NAN;
+        assert!(matches!(
+            validate_mesh(&mesh),
+            Err(DrawError::NonFiniteVertex { vertex: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn patch_pixels_writes_sub_rect() {
+        // 4x3 pixels, patched with 2x2 pixels at column 1 and row 1
+        let mut pixels = vec![0; 4 * 3 * 4];
+        let patch = egui::epaint::AlphaImage {
+            size: [2, 2],
+            pixels: vec![10, 20, 30, 40],
+        };
+        patch_pixels(&mut pixels, 4, &ImageDelta::partial([1, 1], patch));
+
+        let rows: Vec<Vec<u8>> = pixels
+            .chunks_exact(4 * 4)
+            .map(|row| row.chunks_exact(4).map(|pixel| pixel[0]).collect())
+            .collect();
+        assert_eq!(rows, [[0, 0, 0, 0], [0, 10, 20, 0], [0, 30, 40, 0]]);
+    }
+
+    #[test]
+    fn patch_pixels_of_full_delta_replaces_image() {
+        let mut pixels = vec![0; 2 * 4];
+        let image = egui::ColorImage {
+            size: [2, 1],
+            pixels: vec![egui::Color32::RED, egui::Color32::WHITE],
+        };
+        patch_pixels(&mut pixels, 2, &ImageDelta::full(image));
+        assert_eq!(pixels, [255, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn collect_retired_images_of_each_texture_independently() {
+        // The images of two textures replaced by partial updates in consecutive frames
+        let mut garbage = vec![(1, "font atlas"), (2, "user texture")];
+
+        assert!(collect_retired(&mut garbage, 2, 1).is_empty());
+        assert_eq!(collect_retired(&mut garbage, 3, 1), ["font atlas"]);
+        assert_eq!(garbage, [(2, "user texture")]);
+        assert_eq!(collect_retired(&mut garbage, 4, 1), ["user texture"]);
+        assert!(garbage.is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn freed_textures_are_released_by_next_update() {
+        let mut painter = test_painter();
+        let mut builder = test_builder(&painter);
+        let texture_id = TextureId::Managed(1);
+        painter
+            .update_textures(set_delta(texture_id, [4, 4]), &mut builder)
+            .unwrap();
+        assert!(painter.images.contains_key(&texture_id));
+
+        let freed = TexturesDelta {
+            free: vec![texture_id],
+            ..TexturesDelta::default()
+        };
+        let result = painter.update_textures(freed, &mut builder).unwrap();
+        assert_eq!(result.freed, [texture_id]);
+        // The shapes of the frame that frees it can still draw it
+        assert!(painter.texture_set(texture_id).is_some());
+
+        painter
+            .update_textures(TexturesDelta::default(), &mut builder)
+            .unwrap();
+        assert!(!painter.images.contains_key(&texture_id));
+        assert!(painter.texture_set(texture_id).is_none());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn needs_texture_update_only_for_uploads() {
+        let mut painter = test_painter();
+        let texture_id = TextureId::Managed(1);
+        let partial = |texture_id| {
+            let mut textures_delta = TexturesDelta::default();
+            let image = ColorImage::new([1, 1], Color32::RED);
+            textures_delta
+                .set
+                .insert(texture_id, ImageDelta::partial([0, 0], image));
+            textures_delta
+        };
+
+        assert!(!painter.needs_texture_update(&TexturesDelta::default()));
+        assert!(painter.needs_texture_update(&set_delta(texture_id, [4, 4])));
+        // Partial updates of textures without an image are skipped
+        assert!(!painter.needs_texture_update(&partial(texture_id)));
+
+        let mut builder = test_builder(&painter);
+        painter
+            .update_textures(set_delta(texture_id, [4, 4]), &mut builder)
+            .unwrap();
+        assert!(painter.needs_texture_update(&partial(texture_id)));
+        let freed = TexturesDelta {
+            free: vec![texture_id],
+            ..TexturesDelta::default()
+        };
+        assert!(!painter.needs_texture_update(&freed));
+    }
+
+    #[test]
+    fn update_textures_result_keeps_most_significant_change() {
+        let mut result = UpdateTexturesResult::default();
+        assert_eq!(result.change, TextureChange::Unchanged);
+        assert!(!result.changed());
+
+        result.push(TextureId::Managed(1), TextureChange::Updated);
+        assert_eq!(result.change, TextureChange::Updated);
+        assert!(result.changed());
+        assert!(!result.font_atlas_changed);
+
+        result.push(TextureId::default(), TextureChange::Reallocated);
+        result.push(TextureId::Managed(2), TextureChange::Updated);
+        assert_eq!(result.change, TextureChange::Reallocated);
+        assert!(result.font_atlas_changed);
+        assert_eq!(
+            result.set,
+            [
+                TextureId::Managed(1),
+                TextureId::default(),
+                TextureId::Managed(2)
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn update_textures_reports_each_change() {
+        let mut painter = test_painter();
+        let mut builder = test_builder(&painter);
+        let texture_id = TextureId::Managed(1);
+        let mut update = |textures_delta| {
+            painter
+                .update_textures(textures_delta, &mut builder)
+                .unwrap()
+                .change
+        };
+
+        assert_eq!(update(TexturesDelta::default()), TextureChange::Unchanged);
+        assert_eq!(
+            update(set_delta(texture_id, [4, 4])),
+            TextureChange::Reallocated
+        );
+        let mut partial = TexturesDelta::default();
+        let image = ColorImage::new([1, 1], Color32::RED);
+        partial
+            .set
+            .insert(texture_id, ImageDelta::partial([1, 1], image));
+        assert_eq!(update(partial), TextureChange::Updated);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn update_textures_reports_sorted_ids() {
+        let mut painter = test_painter();
+        let mut builder = test_builder(&painter);
+        let ids = [
+            TextureId::Managed(7),
+            TextureId::Managed(2),
+            TextureId::default(),
+            TextureId::Managed(5),
+        ];
+        let mut textures_delta = TexturesDelta::default();
+        for texture_id in ids {
+            textures_delta.set.extend(set_delta(texture_id, [1, 1]).set);
+        }
+        let result = painter
+            .update_textures(textures_delta, &mut builder)
+            .unwrap();
+
+        let mut sorted = ids;
+        sorted.sort();
+        assert_eq!(result.set, sorted);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn empty_textures_get_no_image() {
+        let mut painter = test_painter();
+        let mut builder = test_builder(&painter);
+        let font_atlas = TextureId::default();
+        let result = painter
+            .update_textures(set_delta(font_atlas, [0, 0]), &mut builder)
+            .unwrap();
+
+        assert_eq!(result.change, TextureChange::Unchanged);
+        assert!(!painter.images.contains_key(&font_atlas));
+        assert!(painter.texture_set(font_atlas).is_none());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn from_parts_shares_pipeline_and_sampler() {
+        let first = test_painter();
+        let device = first.device.clone();
+        let parts = |subpass| {
+            Painter::from_parts(
+                device.clone(),
+                first.queue.clone(),
+                first.pipeline.clone(),
+                first.sampler.clone(),
+                subpass,
+            )
+        };
+
+        // A second window with its own, compatible render pass
+        let second = parts(test_subpass(&device)).unwrap();
+        assert!(Arc::ptr_eq(&first.pipeline, &second.pipeline));
+        assert!(Arc::ptr_eq(&first.sampler, &second.sampler));
+
+        let other_format = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: Format::B8G8R8A8_SRGB,
+                    samples: 1,
+                }
+            },
+            pass: { color: [color], depth_stencil: {} }
+        )
+        .unwrap();
+        assert!(matches!(
+            parts(Subpass::from(other_format, 0).unwrap()),
+            Err(PainterCreationError::IncompatibleSubpass { .. })
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn tessellation_options_override_context() {
+        let DrawFixture { mut painter, ctx } = DrawFixture::new();
+        let vertex_count = |painter: &mut Painter| -> usize {
+            let meshes = painter.tessellate(&ctx, DrawFixture::shapes());
+            meshes.iter().map(|m| m.1.vertices.len()).sum()
+        };
+
+        let feathered = vertex_count(&mut painter);
+        painter.set_tessellation_options(Some(TessellationOptions {
+            anti_alias: false,
+            ..*ctx.tessellation_options()
+        }));
+        // Without feathering the rect has no outer ring of transparent vertices
+        assert_eq!(vertex_count(&mut painter), 4);
+        assert!(feathered > 4);
+        // The context's own options are restored after each call
+        assert!(ctx.tessellation_options().anti_alias);
+
+        painter.set_tessellation_options(None);
+        assert_eq!(vertex_count(&mut painter), feathered);
+    }
+
+    #[test]
+    fn texture_options_compare_lod_bias() {
+        let sharp = TextureOptions {
+            lod_bias: -1.0,
+            ..Default::default()
+        };
+        assert_eq!(sharp, sharp);
+        assert_ne!(sharp, TextureOptions::default());
+
+        let keys: HashSet<TextureOptions> = [sharp, sharp, TextureOptions::default()]
+            .into_iter()
+            .collect();
+        assert_eq!(keys.len(), 2);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn sampler_for_caches_per_lod_bias() {
+        let mut painter = test_painter();
+        let sharp = TextureOptions {
+            lod_bias: -1.0,
+            mipmaps: true,
+            ..Default::default()
+        };
+        let first = painter.sampler_for(sharp).unwrap();
+        assert!(Arc::ptr_eq(&first, &painter.sampler_for(sharp).unwrap()));
+
+        let blurry = TextureOptions {
+            lod_bias: 1.0,
+            ..sharp
+        };
+        assert!(!Arc::ptr_eq(&first, &painter.sampler_for(blurry).unwrap()));
+    }
+
+    #[test]
+    fn context_options_apply_max_texture_side() {
+        let options = ContextOptions {
+            max_texture_side: 4096,
+        };
+        let mut raw_input = egui::RawInput::default();
+        options.apply(&mut raw_input);
+        assert_eq!(raw_input.max_texture_side, Some(4096));
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn context_options_match_device() {
+        let painter = test_painter();
+        let limit = painter
+            .device
+            .physical_device()
+            .properties()
+            .max_image_dimension2_d;
+        assert_eq!(painter.context_options().max_texture_side, limit as usize);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn painter_rejects_subpasses_without_blendable_color() {
+        let queue = test_queue();
+        let device = queue.device().clone();
+        let painter = |subpass| Painter::new(device.clone(), queue.clone(), subpass);
+
+        assert!(matches!(
+            painter(depth_only_subpass(&device)),
+            Err(PainterCreationError::IncompatibleSubpass { .. })
+        ));
+
+        let integer = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: Format::R8G8B8A8_UINT,
+                    samples: 1,
+                }
+            },
+            pass: { color: [color], depth_stencil: {} }
+        )
+        .unwrap();
+        assert!(matches!(
+            painter(Subpass::from(integer, 0).unwrap()),
+            Err(PainterCreationError::IncompatibleSubpass { .. })
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn incompatible_subpass_reports_expected_and_found() {
+        let queue = test_queue();
+        let subpass = depth_only_subpass(queue.device());
+        match validate_subpass(&subpass, OutputColorSpace::default()) {
+            Err(PainterCreationError::IncompatibleSubpass { expected, found }) => {
+                assert_eq!(expected, "at least one color attachment");
+                assert_eq!(found, "only a depth/stencil attachment in subpass 0");
+            }
+            other => panic!("expected an incompatible subpass, got {:?}", other),
+        }
+        assert!(
+            validate_subpass(&test_subpass(queue.device()), OutputColorSpace::default()).is_ok()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn contexts_keep_separate_textures() {
+        let mut painter = test_painter();
+        let mut builder = test_builder(&painter);
+        let font_atlas = TextureId::default();
+        let size = |painter: &Painter| match painter.images.get(&font_atlas) {
+            Some(TextureImage::Storage(image)) => Some(image.dimensions().width_height()),
+            _ => None,
+        };
+        painter
+            .update_textures(set_delta(font_atlas, [4, 4]), &mut builder)
+            .unwrap();
+
+        let second = ContextId(1);
+        painter.select_context(second);
+        assert_eq!(painter.selected_context(), second);
+        assert_eq!(size(&painter), None);
+        painter
+            .update_textures(set_delta(font_atlas, [8, 8]), &mut builder)
+            .unwrap();
+        assert_eq!(size(&painter), Some([8, 8]));
+
+        painter.select_context(ContextId::default());
+        assert_eq!(size(&painter), Some([4, 4]));
+
+        painter.remove_context(second);
+        painter.select_context(second);
+        assert_eq!(size(&painter), None);
+    }
+
+    #[test]
+    fn is_drawable_size_needs_positive_finite_sides() {
+        assert!(is_drawable_size([800.0, 600.0]));
+        assert!(!is_drawable_size([0.0, 0.0]));
+        assert!(!is_drawable_size([800.0, 0.0]));
+        assert!(!is_drawable_size([-1.0, 600.0]));
+        assert!(!is_drawable_size([f32::NAN, 600.0]));
+        assert!(!is_drawable_size([f32::INFINITY, 600.0]));
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn zero_sized_targets_get_no_commands() {
+        let DrawFixture { mut painter, ctx } = DrawFixture::new();
+        let mut commands = |size| {
+            painter
+                .draw_commands(size, &ctx, DrawFixture::shapes())
+                .unwrap()
+        };
+
+        assert!(!commands([64.0, 64.0]).is_empty());
+        // e.g. a minimized window
+        assert!(commands([0.0, 0.0]).is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn empty_textures_delta_is_no_op() {
+        let mut painter = test_painter();
+        let mut builder = test_builder(&painter);
+        let font_atlas = TextureId::default();
+        painter
+            .update_textures(set_delta(font_atlas, [4, 4]), &mut builder)
+            .unwrap();
+        let set = painter.texture_set(font_atlas).unwrap();
+
+        let result = painter
+            .update_textures(TexturesDelta::default(), &mut builder)
+            .unwrap();
+        assert_eq!(result, UpdateTexturesResult::default());
+        assert!(!result.changed());
+        assert!(Arc::ptr_eq(&set, &painter.texture_set(font_atlas).unwrap()));
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn render_to_color_image_reads_back_shapes() {
+        let DrawFixture { mut painter, ctx } = DrawFixture::new();
+        let image = painter
+            .render_to_color_image(&ctx, DrawFixture::shapes(), [64, 64], [0.0; 4])
+            .unwrap();
+
+        assert_eq!(image.size, [64, 64]);
+        let pixel = |x: usize, y: usize| image.pixels[y * 64 + x];
+        // Inside and outside of the rect from (10, 10) to (50, 30)
+        assert_eq!(pixel(30, 20), Color32::WHITE);
+        assert_eq!(pixel(30, 50), Color32::TRANSPARENT);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn texture_filter_recreates_sets_of_all_contexts() {
+        let mut painter = test_painter();
+        let mut builder = test_builder(&painter);
+        let font_atlas = TextureId::default();
+        let second = ContextId(1);
+        painter.select_context(second);
+        painter
+            .update_textures(set_delta(font_atlas, [4, 4]), &mut builder)
+            .unwrap();
+        let second_set = painter.texture_set(font_atlas).unwrap();
+        painter.select_context(ContextId::default());
+        painter
+            .update_textures(set_delta(font_atlas, [4, 4]), &mut builder)
+            .unwrap();
+        let set = painter.texture_set(font_atlas).unwrap();
+        let sampler = painter.sampler.clone();
+
+        painter.set_texture_filter(Filter::Nearest).unwrap();
+        assert_eq!(painter.texture_filter, Filter::Nearest);
+        assert_eq!(painter.selected_context(), ContextId::default());
+        assert!(!Arc::ptr_eq(&sampler, &painter.sampler));
+        let nearest_set = painter.texture_set(font_atlas).unwrap();
+        assert!(!Arc::ptr_eq(&set, &nearest_set));
+        painter.select_context(second);
+        assert!(!Arc::ptr_eq(
+            &second_set,
+            &painter.texture_set(font_atlas).unwrap()
+        ));
+        painter.select_context(ContextId::default());
+
+        // Setting the same filter again keeps everything
+        painter.set_texture_filter(Filter::Nearest).unwrap();
+        assert!(Arc::ptr_eq(
+            &nearest_set,
+            &painter.texture_set(font_atlas).unwrap()
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn extended_srgb_linear_needs_float_attachments() {
+        let queue = test_queue();
+        let device = queue.device();
+        let extended = OutputColorSpace::ExtendedSrgbLinear;
+
+        let float = color_subpass(device, Format::R16G16B16A16_SFLOAT);
+        assert!(validate_subpass(&float, extended).is_ok());
+        let srgb = test_subpass(device);
+        assert!(matches!(
+            validate_subpass(&srgb, extended),
+            Err(PainterCreationError::IncompatibleSubpass { .. })
+        ));
+        // Other color spaces still accept it
+        assert!(validate_subpass(&srgb, OutputColorSpace::default()).is_ok());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn render_to_image_adds_usage() {
+        let DrawFixture { mut painter, ctx } = DrawFixture::new();
+        let mut render = |usage| {
+            painter
+                .render_to_image(&ctx, DrawFixture::shapes(), [64, 64], usage)
+                .unwrap()
+        };
+
+        let image = render(ImageUsage::none());
+        assert_eq!(image.format(), Format::R8G8B8A8_SRGB);
+        assert!(!image.inner().image.usage().storage);
+
+        let image = render(ImageUsage {
+            storage: true,
+            ..ImageUsage::none()
+        });
+        // sRGB formats rarely support storage, so the same bytes are stored as UNORM
+        assert_eq!(image.format(), Format::R8G8B8A8_UNORM);
+        let usage = image.inner().image.usage();
+        assert!(usage.storage && usage.color_attachment && usage.sampled && usage.transfer_source);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn painter_draws_without_device_features() {
+        let queue = test_queue_with(Features::none());
+        let features = queue.device().enabled_features();
+        assert!(!features.sampler_anisotropy && !features.fill_mode_non_solid);
+        let subpass = test_subpass(queue.device());
+        let painter = Painter::new(queue.device().clone(), queue, subpass).unwrap();
+
+        let DrawFixture { mut painter, ctx } = DrawFixture::with_painter(painter);
+        let image = painter
+            .render_to_color_image(&ctx, DrawFixture::shapes(), [64, 64], [0.0; 4])
+            .unwrap();
+        assert_eq!(image.pixels[20 * 64 + 30], Color32::WHITE);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn offscreen_draws_keep_stats_and_last_geometry() {
+        let DrawFixture { mut painter, ctx } = DrawFixture::new();
+        painter
+            .render_to_image(&ctx, DrawFixture::shapes(), [64, 64], ImageUsage::none())
+            .unwrap();
+
+        assert_eq!(painter.last_stats(), PaintStats::default());
+        assert!(painter.last_geometry.is_empty());
+    }
+
+    #[test]
+    fn window_viewport_covers_rotated_framebuffer() {
+        let viewport = window_viewport([400.0, 300.0], 1.5, 0);
+        assert_eq!(viewport.origin, [0.0, 0.0]);
+        assert_eq!(viewport.dimensions, [600.0, 450.0]);
+        assert_eq!(
+            window_viewport([400.0, 300.0], 1.5, 1).dimensions,
+            [450.0, 600.0]
+        );
+        assert_eq!(
+            window_viewport([400.0, 300.0], 1.5, 2).dimensions,
+            [600.0, 450.0]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver"]
+    fn cached_draw_sets_its_own_viewport() {
+        let DrawFixture { mut painter, ctx } = DrawFixture::new();
+        assert!(painter.target_viewport.is_none());
+        let cached = painter
+            .draw_cached([64.0, 64.0], &ctx, DrawFixture::shapes())
+            .unwrap();
+
+        let image = StorageImage::with_usage(
+            painter.device.clone(),
+            ImageDimensions::Dim2d {
+                width: 64,
+                height: 64,
+                array_layers: 1,
+            },
+            Format::R8G8B8A8_SRGB,
+            ImageUsage {
+                color_attachment: true,
+                transfer_source: true,
+                ..ImageUsage::none()
+            },
+            ImageCreateFlags::none(),
+            [painter.queue.family()],
+        )
+        .unwrap();
+        let render_pass = painter.pipeline.subpass().render_pass().clone();
+        let framebuffer = Framebuffer::start(render_pass)
+            .add(ImageView::new(image.clone()).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let buffer = CpuAccessibleBuffer::from_iter(
+            painter.device.clone(),
+            BufferUsage::transfer_destination(),
+            false,
+            (0..64 * 64 * 4).map(|_| 0u8),
+        )
+        .unwrap();
+
+        // The primary buffer sets no viewport, the cached one has to
+        let mut builder = test_builder(&painter);
+        builder
+            .begin_render_pass(
+                framebuffer,
+                SubpassContents::SecondaryCommandBuffers,
+                vec![[0.0; 4].into()],
+            )
+            .unwrap();
+        builder.execute_commands(cached).unwrap();
+        builder.end_render_pass().unwrap();
+        builder.copy_image_to_buffer(image, buffer.clone()).unwrap();
+        sync::now(painter.device.clone())
+            .then_execute(painter.queue.clone(), builder.build().unwrap())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let pixels = buffer.read().unwrap();
+        let pixel = |x: usize, y: usize| &pixels[(y * 64 + x) * 4..][..4];
+        // Inside and outside of the rect from (10, 10) to (50, 30)
+        assert_eq!(pixel(30, 20), [255, 255, 255, 255]);
+        assert_eq!(pixel(30, 50), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn last_upload_compares_pixels_after_hash() {
+        let image = ImageData::Color(ColorImage::new([2, 2], Color32::WHITE));
+        let options = TextureOptions::default();
+        let hash = hash_upload(&image, options);
+        let last = LastUpload {
+            hash,
+            options,
+            image: image.clone(),
+        };
+        assert!(last.matches(hash, &image, options));
+
+        // A colliding hash of other pixels or options still uploads
+        let other = ImageData::Color(ColorImage::new([2, 2], Color32::RED));
+        assert!(!last.matches(hash, &other, options));
+        let mipmapped = TextureOptions {
+            mipmaps: true,
+            ..options
+        };
+        assert!(!last.matches(hash, &image, mipmapped));
+        assert!(!last.matches(hash ^ 1, &image, options));
+    }
+
+    #[test]
+    fn merge_target_needs_same_clip_and_texture() {
+        let clip = Rect::from_min_max(pos2(0.0, 0.0), pos2(100.0, 100.0));
+        let bounds = Rect::from_min_max(pos2(0.0, 0.0), pos2(10.0, 10.0));
+        let batches = [batch(clip, TextureId::Managed(0), bounds)];
+
+        assert_eq!(
+            merge_target(&batches, clip, TextureId::Managed(0), bounds, false),
+            Some(0)
+        );
+        assert_eq!(
+            merge_target(&batches, clip, TextureId::User(1), bounds, false),
+            None
+        );
+        assert_eq!(
+            merge_target(
+                &batches,
+                Rect::EVERYTHING,
+                TextureId::Managed(0),
+                bounds,
+                false
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn merge_target_reorders_past_disjoint_batches() {
+        let clip = Rect::EVERYTHING;
+        let left = Rect::from_min_max(pos2(0.0, 0.0), pos2(10.0, 10.0));
+        let right = Rect::from_min_max(pos2(20.0, 0.0), pos2(30.0, 10.0));
+        let batches = [
+            batch(clip, TextureId::Managed(0), left),
+            batch(clip, TextureId::User(1), right),
+        ];
+
+        // Only merged into the first batch if the mesh doesn't overlap the second one
+        assert_eq!(
+            merge_target(&batches, clip, TextureId::Managed(0), left, false),
+            None
+        );
+        assert_eq!(
+            merge_target(&batches, clip, TextureId::Managed(0), left, true),
+            Some(0)
+        );
+        assert_eq!(
+            merge_target(&batches, clip, TextureId::Managed(0), right, true),
+            None
+        );
+    }
+
+    #[test]
+    fn rotate_scissor_quarter_turns() {
+        let scissor = Scissor {
+            origin: [10, 5],
+            dimensions: [20, 10],
+        };
+        let rotate = |turns| {
+            let rotated = rotate_scissor(scissor, [100.0, 50.0], 1.0, turns);
+            (rotated.origin, rotated.dimensions)
+        };
+
+        assert_eq!(rotate(0), ([10, 5], [20, 10]));
+        assert_eq!(rotate(1), ([35, 10], [10, 20]));
+        assert_eq!(rotate(2), ([70, 35], [20, 10]));
+        assert_eq!(rotate(3), ([5, 70], [10, 20]));
+        assert_eq!(rotate(4), rotate(0));
+    }
+
+    #[test]
+    fn rotate_scissor_keeps_partial_pixels() {
+        // 10.4 points cover 11 pixels, the last one partially
+        let window = [10.4, 10.4];
+        let clip = Rect::from_min_max(pos2(0.0, 0.0), pos2(10.4, 10.4));
+        let scissor = clip_to_scissor(clip, window, 1.0).unwrap();
+        assert_eq!(scissor.dimensions, [11, 11]);
+
+        for turns in 1..4 {
+            let rotated = rotate_scissor(scissor, window, 1.0, turns);
+            assert_eq!((rotated.origin, rotated.dimensions), ([0, 0], [11, 11]));
+        }
+        let right = Scissor {
+            origin: [10, 0],
+            dimensions: [1, 11],
+        };
+        let rotated = rotate_scissor(right, window, 1.0, 2);
+        assert_eq!((rotated.origin, rotated.dimensions), ([0, 0], [1, 11]));
+    }
+
+    #[test]
+    fn downsample_pixels_averages_quads() {
+        #[rustfmt::skip]
+        let pixels = [
+            0, 0, 0, 255,   200, 200, 200, 255,
+            0, 0, 0, 255,   200, 200, 200, 255,
+        ];
+        assert_eq!(
+            downsample_pixels(&pixels, [2, 2], false),
+            vec![100, 100, 100, 255]
+        );
+
+        // Black and white average to half the light, not half the sRGB value
+        let srgb = downsample_pixels(&[0, 0, 0, 255, 255, 255, 255, 255], [2, 1], true);
+        let half = gamma_u8_from_linear_f32(0.5);
+        assert_eq!(srgb, vec![half, half, half, 255]);
+    }
+
+    #[test]
+    fn downsample_pixels_keeps_one_pixel() {
+        assert_eq!(
+            downsample_pixels(&[1, 2, 3, 4], [1, 1], false),
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(downsample_pixels(&[0; 3 * 4], [3, 1], false).len(), 4);
+    }
+}
@@ -0,0 +1,409 @@
+//! Rendering the gui into an offscreen image, without a swapchain or render pass of your own.
+use std::sync::Arc;
+
+use egui::epaint::ClippedShape;
+use egui::{Color32, ColorImage, Context};
+use thiserror::Error;
+use vulkano::buffer::cpu_access::ReadLockError;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::pool::CommandPoolBuilderAlloc;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, AutoCommandBufferBuilderContextError, BeginRenderPassError,
+    BuildError, CommandBufferExecError, CommandBufferUsage, CopyBufferImageError,
+    PrimaryAutoCommandBuffer, SubpassContents,
+};
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewCreationError};
+use vulkano::image::{
+    ImageAccess, ImageCreateFlags, ImageCreationError, ImageDimensions, ImageUsage, StorageImage,
+};
+use vulkano::memory::DeviceMemoryAllocError;
+use vulkano::pipeline::graphics::viewport::Viewport;
+use vulkano::pipeline::graphics::{GraphicsPipeline, GraphicsPipelineCreationError};
+use vulkano::render_pass::{
+    Framebuffer, FramebufferCreationError, RenderPass, RenderPassCreationError, Subpass,
+};
+use vulkano::swapchain::SurfaceTransform;
+use vulkano::sync::{self, FlushError, GpuFuture};
+use vulkano::OomError;
+
+use crate::{
+    create_pipeline_variant, DrawError, OutputColorSpace, Painter, PipelineConfig, PipelineVariant,
+    PreparedGeometry,
+};
+
+/// Format of the images created by [`Painter::render_to_image`]
+const OFFSCREEN_FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+/// Format of offscreen images with storage usage, which sRGB formats rarely support,
+/// holding the same sRGB encoded bytes
+const OFFSCREEN_STORAGE_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// Error returned by [`Painter::render_to_image`], [`Painter::render_to_color_image`]
+/// and [`Painter::draw_to_intermediate`].
+#[derive(Error, Debug)]
+pub enum RenderToImageError {
+    #[error(transparent)]
+    CreateRenderPassFailed(#[from] RenderPassCreationError),
+    #[error(transparent)]
+    CreatePipelineFailed(#[from] GraphicsPipelineCreationError),
+    #[error(transparent)]
+    CreateImageFailed(#[from] ImageCreationError),
+    #[error(transparent)]
+    CreateImageViewFailed(#[from] ImageViewCreationError),
+    #[error(transparent)]
+    CreateFramebufferFailed(#[from] FramebufferCreationError),
+    #[error(transparent)]
+    CreateCommandBufferFailed(#[from] OomError),
+    #[error(transparent)]
+    BeginRenderPassFailed(#[from] BeginRenderPassError),
+    #[error(transparent)]
+    DrawFailed(#[from] DrawError),
+    #[error(transparent)]
+    EndRenderPassFailed(#[from] AutoCommandBufferBuilderContextError),
+    #[error(transparent)]
+    BuildFailed(#[from] BuildError),
+    #[error(transparent)]
+    ExecuteFailed(#[from] CommandBufferExecError),
+    #[error(transparent)]
+    FlushFailed(#[from] FlushError),
+    #[error(transparent)]
+    CreateBufferFailed(#[from] DeviceMemoryAllocError),
+    #[error(transparent)]
+    CopyImageFailed(#[from] CopyBufferImageError),
+    #[error(transparent)]
+    ReadFailed(#[from] ReadLockError),
+}
+
+impl Painter {
+    /// Renders the gui into a new `R8G8B8A8_SRGB` image with the given dimensions in pixels,
+    /// e.g. to create thumbnails of the gui.
+    /// Submits its own command buffer to the painter's queue and waits for it to finish.
+    ///
+    /// The image can always be used as a color attachment, sampled and copied from;
+    /// `usage` adds to these, e.g. `ImageUsage { storage: true, ..ImageUsage::none() }`
+    /// to read the gui in a compute shader. Pass `ImageUsage::none()` for just the defaults.
+    /// With storage usage the image has the format `R8G8B8A8_UNORM` instead,
+    /// holding the same sRGB encoded values, since sRGB formats rarely support storage.
+    ///
+    /// The textures used by the shapes have to be uploaded with [`Painter::update_textures`]
+    /// in a command buffer that was submitted before calling this.
+    pub fn render_to_image(
+        &mut self,
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+        dimensions: [u32; 2],
+        usage: ImageUsage,
+    ) -> Result<Arc<StorageImage>, RenderToImageError> {
+        let (image, _) = self.render_offscreen(
+            egui_ctx,
+            clipped_shapes,
+            dimensions,
+            [0.0, 0.0, 0.0, 0.0],
+            usage,
+            false,
+        )?;
+        Ok(image)
+    }
+
+    /// Like [`Painter::render_to_image`], but clears the image to `clear_color` (linear RGBA)
+    /// first and reads the result back to the CPU, e.g. for screenshots or golden-image tests.
+    /// Doesn't need a window or swapchain, so it also works on headless devices.
+    ///
+    /// Colors in the returned image are sRGB encoded with premultiplied alpha, like egui's.
+    pub fn render_to_color_image(
+        &mut self,
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+        dimensions: [u32; 2],
+        clear_color: [f32; 4],
+    ) -> Result<ColorImage, RenderToImageError> {
+        let (_, readback) = self.render_offscreen(
+            egui_ctx,
+            clipped_shapes,
+            dimensions,
+            clear_color,
+            ImageUsage::none(),
+            true,
+        )?;
+        let buffer = readback.unwrap();
+        let bytes = buffer.read()?;
+
+        let pixels = bytes
+            .chunks_exact(4)
+            .map(|p| Color32::from_rgba_premultiplied(p[0], p[1], p[2], p[3]))
+            .collect();
+        Ok(ColorImage {
+            size: [dimensions[0] as usize, dimensions[1] as usize],
+            pixels,
+        })
+    }
+
+    /// Render the gui into a new image in its own submission, optionally copying the image
+    /// into a host visible buffer, and wait for it to finish
+    fn render_offscreen(
+        &mut self,
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+        dimensions: [u32; 2],
+        clear_color: [f32; 4],
+        usage: ImageUsage,
+        read_back: bool,
+    ) -> Result<(Arc<StorageImage>, Option<Arc<CpuAccessibleBuffer<[u8]>>>), RenderToImageError>
+    {
+        let format = if usage.storage {
+            OFFSCREEN_STORAGE_FORMAT
+        } else {
+            OFFSCREEN_FORMAT
+        };
+        let (render_pass, pipeline) = self.offscreen_pass(format)?;
+        let image = create_offscreen_image(self, dimensions, format, usage)?;
+        let framebuffer = Framebuffer::start(render_pass)
+            .add(ImageView::new(image.clone())?)?
+            .build()?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.device.clone(),
+            self.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        // Geometry has to be written before the render pass in case it's copied to device memory
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        let geometry = self.prepare_geometry(&mut builder, &clipped_meshes)?;
+
+        builder.begin_render_pass(
+            framebuffer,
+            SubpassContents::Inline,
+            vec![clear_color.into()],
+        )?;
+        // The viewport is dynamic state, set once for all draw calls
+        builder.set_viewport(
+            0,
+            [Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                depth_range: 0.0..1.0,
+            }],
+        );
+
+        let window_size_points = size_in_points(dimensions, self.pixels_per_point);
+        self.record_unrotated(&mut builder, pipeline, window_size_points, geometry)?;
+
+        builder.end_render_pass()?;
+
+        let readback = if read_back {
+            let buffer = CpuAccessibleBuffer::from_iter(
+                self.device.clone(),
+                BufferUsage::transfer_destination(),
+                false,
+                (0..dimensions[0] * dimensions[1] * 4).map(|_| 0u8),
+            )?;
+            builder.copy_image_to_buffer(image.clone(), buffer.clone())?;
+            Some(buffer)
+        } else {
+            None
+        };
+
+        let command_buffer = builder.build()?;
+
+        sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        Ok((image, readback))
+    }
+
+    /// Draws the gui into an image owned by the painter, with `scale` times the resolution of
+    /// the window, e.g. 0.5 to save fill rate on high resolution screens,
+    /// and returns a view of it to composite onto the window with your own upscaling.
+    /// The image has the format `R8G8B8A8_SRGB` and is cleared to transparent first.
+    ///
+    /// Records into `builder`, which has to be outside of a render pass.
+    /// The image is recreated when `window_size_pixels` or `scale` change,
+    /// otherwise the same image is drawn into every frame.
+    /// egui's input and `pixels_per_point` stay those of the window,
+    /// the scissors and pixel snapping use the resolution of the image.
+    pub fn draw_to_intermediate<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        window_size_pixels: [u32; 2],
+        scale: f32,
+        egui_ctx: &Context,
+        clipped_shapes: Vec<ClippedShape>,
+    ) -> Result<Arc<ImageView<Arc<StorageImage>>>, RenderToImageError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let dimensions = [
+            ((window_size_pixels[0] as f32 * scale).round() as u32).max(1),
+            ((window_size_pixels[1] as f32 * scale).round() as u32).max(1),
+        ];
+        let (render_pass, pipeline) = self.offscreen_pass(OFFSCREEN_FORMAT)?;
+        let (view, framebuffer) = match &self.intermediate {
+            Some((view, framebuffer)) if view.image().dimensions().width_height() == dimensions => {
+                (view.clone(), framebuffer.clone())
+            }
+            // Frames still using the old image keep it alive until they are done
+            _ => {
+                let image =
+                    create_offscreen_image(self, dimensions, OFFSCREEN_FORMAT, ImageUsage::none())?;
+                let view = ImageView::new(image)?;
+                let framebuffer = Framebuffer::start(render_pass).add(view.clone())?.build()?;
+                self.intermediate = Some((view.clone(), framebuffer.clone()));
+                (view, framebuffer)
+            }
+        };
+
+        // Geometry has to be written before the render pass in case it's copied to device memory
+        let clipped_meshes = self.tessellate(egui_ctx, clipped_shapes);
+        let geometry = self.prepare_geometry(builder, &clipped_meshes)?;
+
+        builder.begin_render_pass(
+            framebuffer,
+            SubpassContents::Inline,
+            vec![[0.0, 0.0, 0.0, 0.0].into()],
+        )?;
+        builder.set_viewport(
+            0,
+            [Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                depth_range: 0.0..1.0,
+            }],
+        );
+
+        // The gui keeps the size of the window in points, drawn with fewer pixels per point
+        let window_size_points = size_in_points(window_size_pixels, self.pixels_per_point);
+        let pixels_per_point = self.pixels_per_point;
+        self.pixels_per_point *= scale;
+        let result = self.record_unrotated(builder, pipeline, window_size_points, geometry);
+        self.pixels_per_point = pixels_per_point;
+        result?;
+
+        builder.end_render_pass()?;
+        Ok(view)
+    }
+
+    /// Get the render pass and pipeline to draw into offscreen images with the given format,
+    /// creating them if necessary
+    fn offscreen_pass(
+        &mut self,
+        format: Format,
+    ) -> Result<(Arc<RenderPass>, Arc<GraphicsPipeline>), RenderToImageError> {
+        if let Some(offscreen) = self.offscreen.get(&format) {
+            return Ok(offscreen.clone());
+        }
+        let render_pass = vulkano::single_pass_renderpass!(
+            self.device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+            pass: { color: [color], depth_stencil: {} }
+        )?;
+        let config = PipelineConfig {
+            color_space: match format {
+                OFFSCREEN_FORMAT => OutputColorSpace::SrgbAttachment,
+                _ => OutputColorSpace::EncodeSrgbInShader,
+            },
+            ..PipelineConfig::default()
+        };
+        // Offscreen pipelines have no variants, so they push textures themselves
+        let variant = PipelineVariant {
+            push_descriptors: self.push_descriptors,
+            ..PipelineVariant::default()
+        };
+        let pipeline = create_pipeline_variant(
+            self.device.clone(),
+            Subpass::from(render_pass.clone(), 0).unwrap(),
+            config,
+            variant,
+            None,
+        )?;
+        self.offscreen
+            .insert(format, (render_pass.clone(), pipeline.clone()));
+        Ok((render_pass, pipeline))
+    }
+
+    /// Whether the pipeline draws into offscreen images, whose color space differs from the
+    /// painter's, so it has no variants
+    pub(crate) fn is_offscreen_pipeline(&self, pipeline: &Arc<GraphicsPipeline>) -> bool {
+        self.offscreen
+            .values()
+            .any(|(_, offscreen)| Arc::ptr_eq(offscreen, pipeline))
+    }
+
+    /// Records prepared geometry into the whole offscreen image,
+    /// which is never pre-rotated, ignoring the surface transform and target viewport.
+    /// Stats, the geometry for [`Painter::draw_unchanged`] and freed textures are left
+    /// to the draws into the window.
+    fn record_unrotated<P>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<P::Alloc>, P>,
+        pipeline: Arc<GraphicsPipeline>,
+        window_size_points: [f32; 2],
+        geometry: PreparedGeometry,
+    ) -> Result<(), DrawError>
+    where
+        P: CommandPoolBuilderAlloc,
+    {
+        let surface_transform =
+            std::mem::replace(&mut self.surface_transform, SurfaceTransform::Identity);
+        let target_viewport = self.target_viewport.take();
+        let result = self
+            .record_geometry(builder, pipeline, window_size_points, geometry)
+            .map(|_| ());
+        self.surface_transform = surface_transform;
+        self.target_viewport = target_viewport;
+        result
+    }
+}
+
+/// Size of an image in points, which the gui is laid out in
+fn size_in_points(dimensions: [u32; 2], pixels_per_point: f32) -> [f32; 2] {
+    dimensions.map(|side| side as f32 / pixels_per_point)
+}
+
+/// Create an image to draw the gui into that can be sampled and copied from,
+/// with additional usage
+fn create_offscreen_image(
+    painter: &Painter,
+    dimensions: [u32; 2],
+    format: Format,
+    usage: ImageUsage,
+) -> Result<Arc<StorageImage>, ImageCreationError> {
+    StorageImage::with_usage(
+        painter.device.clone(),
+        ImageDimensions::Dim2d {
+            width: dimensions[0],
+            height: dimensions[1],
+            array_layers: 1,
+        },
+        format,
+        ImageUsage {
+            color_attachment: true,
+            sampled: true,
+            transfer_source: true,
+            ..usage
+        },
+        ImageCreateFlags::none(),
+        [painter.queue.family()],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_in_points_divides_by_scale() {
+        assert_eq!(size_in_points([200, 100], 2.0), [100.0, 50.0]);
+        assert_eq!(size_in_points([300, 150], 1.5), [200.0, 100.0]);
+    }
+}
@@ -0,0 +1,51 @@
+//! Pushing the texture of each draw call into the command buffer with `VK_KHR_push_descriptor`,
+//! instead of allocating a descriptor set for every texture.
+use std::sync::Arc;
+
+use egui::TextureId;
+use vulkano::device::Device;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::graphics::GraphicsPipeline;
+use vulkano::sampler::Sampler;
+
+use crate::{DrawError, Painter, PipelineVariant};
+
+/// View and sampler a texture is pushed with
+pub(crate) type PushedTexture = (Arc<dyn ImageViewAbstract>, Arc<Sampler>);
+
+/// Whether the device has `VK_KHR_push_descriptor` enabled
+pub(crate) fn is_supported(device: &Device) -> bool {
+    device.enabled_extensions().khr_push_descriptor
+}
+
+impl Painter {
+    /// Get the view and sampler to push for a texture of the selected context,
+    /// `None` without push descriptors or if it's drawn with a set of the user's
+    pub(crate) fn pushed_texture(&self, texture_id: TextureId) -> Option<PushedTexture> {
+        let texture_id = match self.font_texture_alias {
+            Some(alias) if alias == texture_id => TextureId::default(),
+            _ => texture_id,
+        };
+        self.texture_views.get(&texture_id).cloned()
+    }
+
+    /// Get the variant of a pipeline whose set 0 is a push descriptor set if textures are pushed.
+    /// Offscreen pipelines are created with a push descriptor set themselves.
+    pub(crate) fn push_descriptor_pipeline(
+        &mut self,
+        pipeline: &Arc<GraphicsPipeline>,
+    ) -> Result<Option<Arc<GraphicsPipeline>>, DrawError> {
+        if !self.push_descriptors {
+            return Ok(None);
+        }
+        if self.is_offscreen_pipeline(pipeline) {
+            return Ok(Some(pipeline.clone()));
+        }
+        let variant = PipelineVariant {
+            push_descriptors: true,
+            wireframe: self.wireframe,
+            ..PipelineVariant::default()
+        };
+        Ok(Some(self.pipeline_variant(pipeline, variant)?))
+    }
+}
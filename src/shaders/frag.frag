@@ -2,9 +2,37 @@
 
 layout(location = 0) in vec4 inColor;
 layout(location = 1) in vec2 inUV;
+layout(location = 2) flat in uint inTextureIndex;
+// Not used by the painter's shader, custom fragment shaders can declare them for animations
+layout(location = 3) flat in float inTime;
+layout(location = 4) flat in uint inFrameIndex;
 
 layout(location = 0) out vec4 outColor;
 
+// Compiled a second time with BINDLESS defined, to sample one array of all textures
+// instead of a descriptor set per texture
+#ifdef BINDLESS
+layout(binding = 0, set = 0) uniform sampler2D textures[TEXTURE_SLOTS];
+#define TEXTURE textures[inTextureIndex]
+#else
 layout(binding = 0, set = 0) uniform sampler2D font_texture;
+#define TEXTURE font_texture
+#endif
 
-void main() { outColor = inColor * texture(font_texture, inUV); }
+// Encode the output to sRGB, for attachments that don't do that in hardware
+layout(constant_id = 0) const bool encode_srgb = false;
+
+// 0-1 sRGB  from  0-1 linear
+vec3 srgb_from_linear(vec3 rgb) {
+    bvec3 cutoff = lessThan(rgb, vec3(0.0031308));
+    vec3 lower = rgb * vec3(12.92);
+    vec3 higher = vec3(1.055) * pow(rgb, vec3(1.0 / 2.4)) - vec3(0.055);
+    return mix(higher, lower, cutoff);
+}
+
+void main() {
+    outColor = inColor * texture(TEXTURE, inUV);
+    if (encode_srgb) {
+        outColor.rgb = srgb_from_linear(outColor.rgb);
+    }
+}
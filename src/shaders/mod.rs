@@ -8,6 +8,15 @@ pub mod vs {
     }
 }
 
+/// The vertex shader for multiview subpasses, with per-view offset and scale
+pub mod vs_multiview {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/vert.vert",
+        define: [("MULTIVIEW", "1")]
+    }
+}
+
 /// The fragment shader
 pub mod fs {
     vulkano_shaders::shader! {
@@ -15,3 +24,13 @@ pub mod fs {
         path: "src/shaders/frag.frag"
     }
 }
+
+/// The fragment shader for bindless textures, sampling an array with one slot per texture.
+/// `TEXTURE_SLOTS` has to match `bindless::TEXTURE_SLOTS`.
+pub mod fs_bindless {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/frag.frag",
+        define: [("BINDLESS", "1"), ("TEXTURE_SLOTS", "256")]
+    }
+}
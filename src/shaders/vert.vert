@@ -1,13 +1,49 @@
 #version 450
 
+// Compiled a second time with MULTIVIEW defined for multiview subpasses,
+// which needs the multiview device feature
+#ifdef MULTIVIEW
+#extension GL_EXT_multiview : require
+#define VIEW_INDEX gl_ViewIndex
+#else
+#define VIEW_INDEX 0
+#endif
+
 layout(location = 0) in vec2 pos;
 layout(location = 1) in vec2 uv;
-layout(location = 2) in vec4 color;
+// Premultiplied sRGBA color, one byte per channel
+layout(location = 2) in uint color;
 
 layout(location = 0) out vec4 outColor;
 layout(location = 1) out vec2 outUV;
+// Slot of the texture array to sample, only read by the bindless fragment shader
+layout(location = 2) flat out uint outTextureIndex;
+// Time and frame index for animated effects in custom fragment shaders
+layout(location = 3) flat out float outTime;
+layout(location = 4) flat out uint outFrameIndex;
+
+// Vertex colors are already linear and must not be decoded from sRGB
+#define FLAG_LINEAR_COLORS 1u
+// Round vertex positions to whole physical pixels
+#define FLAG_SNAP_TO_PIXELS 2u
 
-layout(push_constant) uniform PushConstants { vec2 screen_size; } pushConstants;
+layout(push_constant) uniform PushConstants {
+    // Applied to the clip space position, identity unless drawing onto e.g. a quad in 3D
+    mat4 transform;
+    // Clip space offset in xy and scale in zw for each view of a multiview subpass
+    vec4 view_offset_scale[2];
+    vec2 screen_size;
+    // FLAG_* bits, packed so the block fits the 128 bytes every device supports
+    uint flags;
+    float pixels_per_point;
+    // Linear brightness of white, 1.0 unless drawing to an HDR attachment
+    float white_level;
+    // Seconds and frames since a point of the caller's choosing, for animated effects
+    float time_seconds;
+    uint frame_index;
+    // Slot of the texture in the bindless texture array, pushed separately before each draw
+    uint texture_index;
+} pushConstants;
 
 // 0-1 linear  from  0-255 sRGB
 vec3 linear_from_srgb(vec3 srgb) {
@@ -22,9 +58,20 @@ vec4 linear_from_srgba(vec4 srgba) {
 }
 
 void main() {
-gl_Position =
-      vec4(2.0 * pos.x / pushConstants.screen_size.x - 1.0,
-           2.0 * pos.y / pushConstants.screen_size.y - 1.0, 0.0, 1.0);
-  outColor = linear_from_srgba(color);
+  vec2 position = pos;
+  if ((pushConstants.flags & FLAG_SNAP_TO_PIXELS) != 0) {
+    position = round(position * pushConstants.pixels_per_point) / pushConstants.pixels_per_point;
+  }
+  gl_Position = pushConstants.transform *
+      vec4(2.0 * position.x / pushConstants.screen_size.x - 1.0,
+           2.0 * position.y / pushConstants.screen_size.y - 1.0, 0.0, 1.0);
+  vec4 view = pushConstants.view_offset_scale[VIEW_INDEX];
+  gl_Position.xy = gl_Position.xy * view.zw + view.xy * gl_Position.w;
+  vec4 vertexColor = unpackUnorm4x8(color);
+  outColor = (pushConstants.flags & FLAG_LINEAR_COLORS) != 0 ? vertexColor : linear_from_srgba(vertexColor);
+  outColor.rgb *= pushConstants.white_level;
   outUV = uv;
+  outTextureIndex = pushConstants.texture_index;
+  outTime = pushConstants.time_seconds;
+  outFrameIndex = pushConstants.frame_index;
 }